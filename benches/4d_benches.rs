@@ -1,7 +1,7 @@
 use std::{collections::BTreeMap, time::Duration};
 
 use criterion::{criterion_group, criterion_main, Bencher, BenchmarkId, Criterion};
-use quadtree::{morton_4, ZOrderIndexer};
+use quadtree::{morton_4, morton_4_batch, ZOrderIndexer};
 use rand::Rng;
 use rstar::{primitives::Rectangle, RTree, RTreeObject};
 
@@ -52,9 +52,12 @@ fn z_order_benchmark(b: &mut Bencher<'_>, num_points: usize, rect_size: i32) {
     let points = generate_random_4d_points(num_points, BOUNDS);
 
     // Create a Morton-encoded Z-order tree
-    let mut z_ordered_points: Vec<_> = points.clone();
-    z_ordered_points.sort_by_key(|p| morton_4(p.0 as u16, p.1 as u16, p.2 as u16, p.3 as u16));
-    let key: Vec<_> = z_ordered_points.iter().map(|p| morton_4(p.0 as u16, p.1 as u16, p.2 as u16, p.3 as u16)).collect();
+    let coordinates: Vec<_> = points.iter().map(|p| (p.0 as u16, p.1 as u16, p.2 as u16, p.3 as u16)).collect();
+    let mut keys = vec![0; points.len()];
+    morton_4_batch(&coordinates, &mut keys);
+    let mut z_ordered: Vec<_> = keys.into_iter().zip(points).collect();
+    z_ordered.sort_by_key(|e| e.0);
+    let (key, z_ordered_points): (Vec<_>, Vec<_>) = z_ordered.into_iter().unzip();
     
     b.iter(|| {
         let mut rect = (rng.gen_range(0..BOUNDS.end), rng.gen_range(0..BOUNDS.end), 0, 0);
@@ -108,8 +111,7 @@ fn z_order_btree_benchmark(b: &mut Bencher<'_>, num_points: usize, rect_size: i3
         let mut cursor = z_ordered_points.range(min..=max);
         let mut results = Vec::new();
         let mut misses = 0;
-        loop {
-            let Some((&z, p)) = cursor.next() else { break };
+        while let Some((&z, p)) = cursor.next() {
             if !zi.contains(z) {
                 misses += 1;
                 if misses < 32 { continue };
@@ -3,7 +3,11 @@
 use std::time::Duration;
 
 use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
-use quadtree::{generate_random_points, QuadTree};
+use quadtree::{
+    generate_random_points, knn_join, morton_2, morton_2_batch, morton_4, morton_4_batch, morton_reverse_2,
+    morton_reverse_2_batch, morton_reverse_4, morton_reverse_4_batch, CountedQuadTree, HilbertQuadTree, Point4,
+    QuadTree, Quantizer,
+};
 use rand::Rng;
 
 fn benchmark_count_neighbors(c: &mut Criterion) {
@@ -48,6 +52,29 @@ fn benchmark_count_neighbors(c: &mut Criterion) {
         );
     }
     drop(cn);
+    let mut all = c.benchmark_group("all_neighbor_counts");
+    all.warm_up_time(Duration::from_millis(100));
+    all.measurement_time(Duration::from_millis(500));
+    for size in sizes.iter().copied() {
+        let points = generate_random_points(size, 1e3);
+        let mut quadtree = QuadTree::new();
+        for point in &points {
+            quadtree.insert(*point);
+        }
+        all.bench_with_input(
+            BenchmarkId::new("count_within_distance", size),
+            &quadtree,
+            |b, quadtree| b.iter(|| quadtree.iter()
+                .map(|(p, _)| quadtree.count_within_distance(p, 1e1))
+                .collect::<Vec<_>>())
+        );
+        all.bench_with_input(
+            BenchmarkId::new("neighbor_counts", size),
+            &quadtree,
+            |b, quadtree| b.iter(|| quadtree.neighbor_counts(1e1))
+        );
+    }
+    drop(all);
     let mut knn = c.benchmark_group("10_nearest_neighbors");
     for size in sizes.iter().copied() {
         knn.bench_with_input(
@@ -142,6 +169,220 @@ fn benchmark_count_neighbors(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, benchmark_count_neighbors);
+fn benchmark_knn_join(c: &mut Criterion) {
+    let mut join = c.benchmark_group("knn_join");
+    join.warm_up_time(Duration::from_millis(100));
+    join.measurement_time(Duration::from_millis(500));
+    let size = 10000;
+    for k in [1, 10, 100] {
+        join.bench_with_input(
+            BenchmarkId::new("knn_join", k),
+            &size,
+            |b, &size| {
+                let mut a = QuadTree::new();
+                for point in generate_random_points(size, 1e6) {
+                    a.insert(point);
+                }
+                let mut quadtree = QuadTree::new();
+                for point in generate_random_points(size, 1e6) {
+                    quadtree.insert(point);
+                }
+                b.iter(|| knn_join(&a, &quadtree, k).map(|(_, n)| n.len()).sum::<usize>());
+            }
+        );
+        join.bench_with_input(
+            BenchmarkId::new("knn_join_kd", k),
+            &size,
+            |b, &size| {
+                let a = generate_random_points(size, 1e6);
+                let mut kdtree = kdtree::KdTree::new(2);
+                for point in generate_random_points(size, 1e6) {
+                    kdtree.add([point.0, point.1], ()).unwrap()
+                }
+                fn chebyshev(a: &[f32], b: &[f32]) -> f32 {
+                    let mut r: f32 = 0.0;
+                    for i in 0..a.len().min(b.len()) {
+                        r = r.max((a[i] - b[i]).abs());
+                    }
+                    r
+                }
+                b.iter(|| a.iter()
+                    .map(|p| kdtree.nearest(&[p.0, p.1], k, &chebyshev).unwrap().len())
+                    .sum::<usize>());
+            }
+        );
+    }
+}
+
+fn benchmark_count_in_range(c: &mut Criterion) {
+    let mut count = c.benchmark_group("count_in_range");
+    count.warm_up_time(Duration::from_millis(100));
+    count.measurement_time(Duration::from_millis(500));
+    let points = generate_random_points(100000, 1e3);
+    let mut quadtree = QuadTree::new();
+    let mut counted = CountedQuadTree::default();
+    for point in &points {
+        quadtree.insert(*point);
+        counted.insert(*point);
+    }
+    for size in [1e1, 1e2, 5e2] {
+        let mut rng = rand::thread_rng();
+        let mut rect = move || {
+            let min = (rng.gen_range(0.0..1e3 - size), rng.gen_range(0.0..1e3 - size));
+            (min, (min.0 + size, min.1 + size))
+        };
+        count.bench_function(BenchmarkId::new("query_count", size), |b| {
+            b.iter_batched(&mut rect, |r| quadtree.query_float(r.0, r.1).count(), BatchSize::SmallInput)
+        });
+        count.bench_function(BenchmarkId::new("count_in_range", size), |b| {
+            b.iter_batched(&mut rect, |r| quadtree.count_in_range(r), BatchSize::SmallInput)
+        });
+        count.bench_function(BenchmarkId::new("count_in_range_counted", size), |b| {
+            b.iter_batched(&mut rect, |r| counted.count_in_range(r), BatchSize::SmallInput)
+        });
+    }
+}
+
+fn spread_bits_2_loop(mut input: u32) -> u64 {
+    let mut output = 0;
+    for _ in 0..32 {
+        output = (output << 2) | (input & 1) as u64;
+        input >>= 1;
+    }
+    output.reverse_bits()
+}
+
+fn collapse_bits_2_loop(mut input: u64) -> u32 {
+    let mut output = 0;
+    for _ in 0..32 {
+        output = (output << 1) | (input & 1) as u32;
+        input >>= 2;
+    }
+    output.reverse_bits()
+}
+
+fn benchmark_morton(c: &mut Criterion) {
+    let mut morton = c.benchmark_group("morton");
+    morton.warm_up_time(Duration::from_millis(100));
+    morton.measurement_time(Duration::from_millis(500));
+    let mut rng = rand::thread_rng();
+    let points: Vec<(u32, u32)> = (0..1024).map(|_| (rng.gen(), rng.gen())).collect();
+    let keys: Vec<u64> = (0..1024).map(|_| rng.gen()).collect();
+    morton.bench_function("encode_loop", |b| {
+        b.iter(|| points.iter().map(|p| spread_bits_2_loop(p.0) | spread_bits_2_loop(p.1) >> 1).fold(0, u64::wrapping_add))
+    });
+    morton.bench_function("encode", |b| {
+        b.iter(|| points.iter().map(|p| morton_2(p.0, p.1)).fold(0, u64::wrapping_add))
+    });
+    let mut out = vec![0; points.len()];
+    morton.bench_function("encode_batch", |b| {
+        b.iter(|| { morton_2_batch(&points, &mut out); out[0] })
+    });
+    morton.bench_function("decode_loop", |b| {
+        b.iter(|| keys.iter().map(|&z| collapse_bits_2_loop(z >> 1) ^ collapse_bits_2_loop(z)).fold(0, u32::wrapping_add))
+    });
+    morton.bench_function("decode", |b| {
+        b.iter(|| keys.iter().map(|&z| { let (x, y) = morton_reverse_2(z); x ^ y }).fold(0, u32::wrapping_add))
+    });
+    let mut out = vec![(0, 0); keys.len()];
+    morton.bench_function("decode_batch", |b| {
+        b.iter(|| { morton_reverse_2_batch(&keys, &mut out); out[0] })
+    });
+    let points_4: Vec<Point4> = (0..1024).map(|_| (rng.gen(), rng.gen(), rng.gen(), rng.gen())).collect();
+    morton.bench_function("encode_4", |b| {
+        b.iter(|| points_4.iter().map(|p| morton_4(p.0, p.1, p.2, p.3)).fold(0, u64::wrapping_add))
+    });
+    let mut out = vec![0; points_4.len()];
+    morton.bench_function("encode_4_batch", |b| {
+        b.iter(|| { morton_4_batch(&points_4, &mut out); out[0] })
+    });
+    morton.bench_function("decode_4", |b| {
+        b.iter(|| keys.iter().map(|&z| { let p = morton_reverse_4(z); p.0 ^ p.1 ^ p.2 ^ p.3 }).fold(0, u16::wrapping_add))
+    });
+    let mut out = vec![(0, 0, 0, 0); keys.len()];
+    morton.bench_function("decode_4_batch", |b| {
+        b.iter(|| { morton_reverse_4_batch(&keys, &mut out); out[0] })
+    });
+}
+
+fn benchmark_curves(c: &mut Criterion) {
+    let mut curves = c.benchmark_group("curves");
+    curves.warm_up_time(Duration::from_millis(100));
+    curves.measurement_time(Duration::from_millis(500));
+    let points = generate_random_points(100000, 1e3);
+    let mut zorder = QuadTree::new();
+    let mut hilbert = HilbertQuadTree::default();
+    for point in &points {
+        zorder.insert(*point);
+        hilbert.insert(*point);
+    }
+    let mut rng = rand::thread_rng();
+    let mut rect = move || {
+        let min = (rng.gen_range(0.0..9e2), rng.gen_range(0.0..9e2));
+        (min, (min.0 + 1e2, min.1 + 1e2))
+    };
+    curves.bench_function("query_zorder", |b| {
+        b.iter_batched(&mut rect, |r| zorder.query_float(r.0, r.1).count(), BatchSize::SmallInput)
+    });
+    curves.bench_function("query_hilbert", |b| {
+        b.iter_batched(&mut rect, |r| hilbert.query_float(r.0, r.1).count(), BatchSize::SmallInput)
+    });
+    let mut rng = rand::thread_rng();
+    let mut point = move || (rng.gen_range(0.0..1e3), rng.gen_range(0.0..1e3));
+    curves.bench_function("nearest_zorder", |b| {
+        b.iter_batched(&mut point, |p| zorder.nearest(p).take(10).count(), BatchSize::SmallInput)
+    });
+    curves.bench_function("nearest_hilbert", |b| {
+        b.iter_batched(&mut point, |p| hilbert.nearest(p).take(10).count(), BatchSize::SmallInput)
+    });
+}
+
+fn benchmark_quantizer(c: &mut Criterion) {
+    let mut grid = c.benchmark_group("quantizer");
+    grid.warm_up_time(Duration::from_millis(100));
+    grid.measurement_time(Duration::from_millis(500));
+    let points = generate_random_points(100000, 1e3);
+    let mut ordered = QuadTree::new();
+    let mut quantized = QuadTree::quantized(Quantizer::new(((0.0, 0.0), (1e3, 1e3)), 1e-4));
+    for point in &points {
+        ordered.insert(*point);
+        quantized.insert(*point);
+    }
+    let mut rng = rand::thread_rng();
+    let mut rect = move || {
+        let min = (rng.gen_range(0.0..9e2), rng.gen_range(0.0..9e2));
+        (min, (min.0 + 1e2, min.1 + 1e2))
+    };
+    grid.bench_function("query_ordered_float", |b| {
+        b.iter_batched(&mut rect, |r| ordered.query_float(r.0, r.1).count(), BatchSize::SmallInput)
+    });
+    grid.bench_function("query_quantized", |b| {
+        b.iter_batched(&mut rect, |r| quantized.query_float(r.0, r.1).count(), BatchSize::SmallInput)
+    });
+    let mut rng = rand::thread_rng();
+    let mut point = move || (rng.gen_range(0.0..1e3), rng.gen_range(0.0..1e3));
+    grid.bench_function("nearest_ordered_float", |b| {
+        b.iter_batched(&mut point, |p| ordered.nearest(p).take(10).count(), BatchSize::SmallInput)
+    });
+    grid.bench_function("nearest_quantized", |b| {
+        b.iter_batched(&mut point, |p| quantized.nearest(p).take(10).count(), BatchSize::SmallInput)
+    });
+    grid.bench_function("count_within_distance_ordered_float", |b| {
+        b.iter_batched(&mut point, |p| ordered.count_within_distance(&p, 1e1), BatchSize::SmallInput)
+    });
+    grid.bench_function("count_within_distance_quantized", |b| {
+        b.iter_batched(&mut point, |p| quantized.count_within_distance(&p, 1e1), BatchSize::SmallInput)
+    });
+}
+
+criterion_group!(
+    benches,
+    benchmark_count_neighbors,
+    benchmark_knn_join,
+    benchmark_count_in_range,
+    benchmark_morton,
+    benchmark_curves,
+    benchmark_quantizer
+);
 criterion_main!(benches);
 
@@ -0,0 +1,159 @@
+use crate::{AugmentedMap, Entry, QuadTree, Rect, SpaceFillingCurve};
+
+/// A value that can be combined associatively, with an identity.
+pub trait Monoid: Clone {
+    fn empty() -> Self;
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// The contribution of a single entry to an aggregate.
+pub trait Measure<T>: Monoid {
+    fn measure(item: &T) -> Self;
+
+    /// The largest value measured, if the aggregate keeps track of it.
+    fn upper_bound(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl Monoid for () {
+    fn empty() -> Self {}
+    fn combine(&self, _: &Self) -> Self {}
+}
+
+impl<T> Measure<T> for () {
+    fn measure(_: &T) -> Self {}
+}
+
+impl<A: Monoid, B: Monoid> Monoid for (A, B) {
+    fn empty() -> Self {
+        (A::empty(), B::empty())
+    }
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+impl<T, A: Measure<T>, B: Measure<T>> Measure<T> for (A, B) {
+    fn measure(item: &T) -> Self {
+        (A::measure(item), B::measure(item))
+    }
+    fn upper_bound(&self) -> Option<f64> {
+        self.0.upper_bound().or(self.1.upper_bound())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sum(pub f64);
+
+impl Monoid for Sum {
+    fn empty() -> Self {
+        Sum(0.0)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0 + other.0)
+    }
+}
+
+impl<V: Copy + Into<f64>> Measure<Entry<V>> for Sum {
+    fn measure(item: &Entry<V>) -> Self {
+        Sum(item.1.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Min(pub f64);
+
+impl Monoid for Min {
+    fn empty() -> Self {
+        Min(f64::INFINITY)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Min(self.0.min(other.0))
+    }
+}
+
+impl<V: Copy + Into<f64>> Measure<Entry<V>> for Min {
+    fn measure(item: &Entry<V>) -> Self {
+        Min(item.1.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Max(pub f64);
+
+impl Monoid for Max {
+    fn empty() -> Self {
+        Max(f64::NEG_INFINITY)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Max(self.0.max(other.0))
+    }
+}
+
+impl<V: Copy + Into<f64>> Measure<Entry<V>> for Max {
+    fn measure(item: &Entry<V>) -> Self {
+        Max(item.1.into())
+    }
+    fn upper_bound(&self) -> Option<f64> {
+        Some(self.0)
+    }
+}
+
+/// Count, sum, min and max of the values, from which the mean follows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+impl Monoid for Stats {
+    fn empty() -> Self {
+        Stats { count: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY }
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Stats {
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+impl<V: Copy + Into<f64>> Measure<Entry<V>> for Stats {
+    fn measure(item: &Entry<V>) -> Self {
+        let value = item.1.into();
+        Stats { count: 1, sum: value, min: value, max: value }
+    }
+    fn upper_bound(&self) -> Option<f64> {
+        Some(self.max)
+    }
+}
+
+/// A `QuadTree` whose store keeps the aggregate `M` of every subtree.
+pub type AugmentedQuadTree<V, M> = QuadTree<V, AugmentedMap<Entry<V>, M>>;
+
+impl<V, M: Measure<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, AugmentedMap<Entry<V>, M>, C> {
+    /// Combines the entries inside `rect`. Quadtree cells entirely inside it
+    /// use the aggregates kept by the store, only cells on its border are
+    /// examined point by point.
+    pub fn aggregate_in_range(&self, rect: Rect) -> M {
+        let mut aggregate = M::empty();
+        let mut boundary = M::empty();
+        self.visit_cells(
+            rect,
+            |start, end| aggregate = aggregate.combine(&self.tree.aggregate(start ..= end)),
+            |e| boundary = boundary.combine(&M::measure(e)),
+        );
+        aggregate.combine(&boundary)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::morton::{collapse_bits_2_magic, collapse_bits_4_magic, spread_bits_2_magic, spread_bits_4_magic};
+
+pub type Point4 = (u16, u16, u16, u16);
+
+// The loops only use shifts and masks, so the compiler vectorizes them. The
+// `avx2` copies are the same loops compiled for wider registers, picked at runtime.
+macro_rules! batch {
+    ($(#[$doc:meta])* $name:ident, $input:ty, $output:ty, $body:expr) => {
+        $(#[$doc])*
+        pub fn $name(input: &[$input], output: &mut [$output]) {
+            assert_eq!(input.len(), output.len());
+            #[inline(always)]
+            fn portable(input: &[$input], output: &mut [$output]) {
+                for (o, i) in output.iter_mut().zip(input) {
+                    *o = $body(*i);
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            #[target_feature(enable = "avx2")]
+            unsafe fn avx2(input: &[$input], output: &mut [$output]) {
+                portable(input, output)
+            }
+            #[cfg(target_arch = "x86_64")]
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { avx2(input, output) };
+            }
+            portable(input, output)
+        }
+    };
+}
+
+batch!(
+    /// `morton_2` over a slice, `input` and `output` must have the same length.
+    morton_2_batch, (u32, u32), u64,
+    |(x, y): (u32, u32)| spread_bits_2_magic(x) | spread_bits_2_magic(y) >> 1
+);
+
+batch!(
+    /// `morton_reverse_2` over a slice.
+    morton_reverse_2_batch, u64, (u32, u32),
+    |z: u64| (collapse_bits_2_magic(z >> 1), collapse_bits_2_magic(z))
+);
+
+batch!(
+    /// `morton_4` over a slice.
+    morton_4_batch, Point4, u64,
+    |(x, y, z, w): Point4| {
+        spread_bits_4_magic(x) | spread_bits_4_magic(y) >> 1 | spread_bits_4_magic(z) >> 2 | spread_bits_4_magic(w) >> 3
+    }
+);
+
+batch!(
+    /// `morton_reverse_4` over a slice.
+    morton_reverse_4_batch, u64, Point4,
+    |z: u64| (collapse_bits_4_magic(z >> 3), collapse_bits_4_magic(z >> 2), collapse_bits_4_magic(z >> 1), collapse_bits_4_magic(z))
+);
@@ -0,0 +1,105 @@
+use crate::morton::ordered_float_clamped;
+use crate::{morton_2, morton_add_x, morton_add_y, morton_reverse_2, morton_sub_x, morton_sub_y};
+
+/// A node of the implicit quadtree: every key sharing the Morton `prefix`,
+/// which is `2 * level` bits long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cell {
+    prefix: u64,
+    level: u32,
+}
+
+impl Cell {
+    pub const MAX_LEVEL: u32 = 32;
+
+    pub fn root() -> Self {
+        Cell { prefix: 0, level: 0 }
+    }
+
+    pub fn prefix(&self) -> u64 {
+        self.prefix
+    }
+
+    /// Between 0 for the root and `MAX_LEVEL` for single keys.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// The cell at `level` containing `key`.
+    pub fn from_key(key: u64, level: u32) -> Self {
+        let level = level.min(Self::MAX_LEVEL);
+        Cell { prefix: key.checked_shr(64 - 2 * level).unwrap_or(0), level }
+    }
+
+    /// The cell at `level` in column `x` and row `y` of its `2^level` by `2^level` grid.
+    pub fn from_coordinates(x: u32, y: u32, level: u32) -> Self {
+        let level = level.min(Self::MAX_LEVEL);
+        let x = x.checked_shl(32 - level).unwrap_or(0);
+        let y = y.checked_shl(32 - level).unwrap_or(0);
+        Self::from_key(morton_2(x, y), level)
+    }
+
+    pub fn coordinates(&self) -> (u32, u32) {
+        let (x, y) = morton_reverse_2(self.key_range().0);
+        (x.checked_shr(32 - self.level).unwrap_or(0), y.checked_shr(32 - self.level).unwrap_or(0))
+    }
+
+    /// The first and last keys inside the cell.
+    pub fn key_range(&self) -> (u64, u64) {
+        let min = self.prefix.checked_shl(64 - 2 * self.level).unwrap_or(0);
+        (min, min | u64::MAX.checked_shr(2 * self.level).unwrap_or(0))
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        key.checked_shr(64 - 2 * self.level).unwrap_or(0) == self.prefix
+    }
+
+    pub fn parent(&self) -> Option<Cell> {
+        (self.level > 0).then(|| Cell { prefix: self.prefix >> 2, level: self.level - 1 })
+    }
+
+    /// The four quadrants, in Morton order.
+    pub fn children(&self) -> Option<[Cell; 4]> {
+        (self.level < Self::MAX_LEVEL).then(|| {
+            [0, 1, 2, 3].map(|i| Cell { prefix: self.prefix << 2 | i, level: self.level + 1 })
+        })
+    }
+
+    /// The up to eight cells of the same level sharing an edge or a corner with this one.
+    pub fn neighbors(&self) -> Vec<Cell> {
+        // The prefix is itself a Morton key of the cell's coordinates at this level.
+        let used = u64::MAX.checked_shr(64 - 2 * self.level).unwrap_or(0);
+        let (xs, ys) = (used & 0xAAAA_AAAA_AAAA_AAAA, used & 0x5555_5555_5555_5555);
+        let step = |prefix, d: i32, add: fn(u64, u32) -> u64, sub: fn(u64, u32) -> u64, axis: u64| match d {
+            -1 => (prefix & axis != 0).then(|| sub(prefix, 1)),
+            1 => (prefix & axis != axis).then(|| add(prefix, 1)),
+            _ => Some(prefix),
+        };
+        let mut cells = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            let Some(row) = step(self.prefix, dy, morton_add_y, morton_sub_y, ys) else { continue };
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) { continue };
+                let Some(prefix) = step(row, dx, morton_add_x, morton_sub_x, xs) else { continue };
+                cells.push(Cell { prefix, level: self.level });
+            }
+        }
+        cells
+    }
+
+    /// The region covered by the cell, in the coordinates passed to `morton_2`.
+    pub fn grid_bounds(&self) -> ((u32, u32), (u32, u32)) {
+        let (min, max) = self.key_range();
+        (morton_reverse_2(min), morton_reverse_2(max))
+    }
+
+    /// The region covered by the cell, in the coordinates passed to `ordered_float`.
+    /// Edges among the NaNs past either infinity are clamped to that infinity.
+    pub fn bounds(&self) -> ((f32, f32), (f32, f32)) {
+        let (min, max) = self.grid_bounds();
+        (
+            (ordered_float_clamped(min.0), ordered_float_clamped(min.1)),
+            (ordered_float_clamped(max.0), ordered_float_clamped(max.1)),
+        )
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+use crate::{Cell, Entry, Metric, QuadTree, SpaceFillingCurve, Store, AABB};
+
+/// Labels every point, in curve order, with its DBSCAN cluster, or `None` for noise.
+/// Neighbourhoods are circles of radius `eps`, found with `query_aabb`,
+/// and a point counts towards its own neighbourhood. Every point is noise when
+/// `eps` is negative or NaN.
+pub fn dbscan<V, S: Store<Entry<V>>, C: SpaceFillingCurve>(tree: &QuadTree<V, S, C>, eps: f32, min_pts: usize) -> Vec<Option<usize>> {
+    if eps.is_nan() || eps < 0.0 {
+        return vec![None; tree.len()];
+    }
+    let keys: Vec<u64> = tree.tree.range(..).map(|(k, _)| *k).collect();
+    let points: Vec<(f32, f32)> = tree.iter().map(|(p, _)| *p).collect();
+    let index = |p: &(f32, f32)| keys.binary_search(&tree.key_of(*p).unwrap()).unwrap();
+    let neighbors = |i: usize, found: &mut Vec<usize>| {
+        let p = points[i];
+        found.clear();
+        found.extend(tree.query_aabb(&AABB(p, eps))
+            .filter(|q| Metric::Euclidean.within(&p, q, eps))
+            .map(index));
+    };
+
+    let mut labels = vec![None; points.len()];
+    let mut visited = vec![false; points.len()];
+    let mut queue = VecDeque::new();
+    let mut found = Vec::new();
+    let mut cluster = 0;
+    for i in 0..points.len() {
+        if visited[i] { continue };
+        visited[i] = true;
+        neighbors(i, &mut found);
+        if found.len() < min_pts { continue };
+        labels[i] = Some(cluster);
+        queue.extend(found.drain(..));
+        while let Some(j) = queue.pop_front() {
+            // Noise reached from a core point becomes a border point
+            if labels[j].is_none() {
+                labels[j] = Some(cluster);
+            }
+            if visited[j] { continue };
+            visited[j] = true;
+            neighbors(j, &mut found);
+            if found.len() >= min_pts {
+                queue.extend(found.drain(..));
+            }
+        }
+        cluster += 1;
+    }
+    labels
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellCluster {
+    pub cell: Cell,
+    pub count: usize,
+    pub centroid: (f32, f32),
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl<V, S: Store<Entry<V>>> QuadTree<V, S> {
+    /// Aggregates the points by quadtree cell, `level` levels below the root.
+    /// A cell is a key prefix of `2 * level` bits, so one pass over the tree
+    /// reads the clusters one after the other as runs of keys.
+    pub fn cluster_at_level(&self, level: u32) -> Vec<CellCluster> {
+        let mut clusters = Vec::new();
+        let mut entries = self.tree.range(..).peekable();
+        while let Some((key, _)) = entries.peek() {
+            let cell = Cell::from_key(**key, level);
+            let end = cell.key_range().1;
+            let mut count = 0;
+            let mut sum = (0.0f64, 0.0f64);
+            let mut min = (f32::INFINITY, f32::INFINITY);
+            let mut max = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+            while let Some((_, (p, _))) = entries.next_if(|(k, _)| **k <= end) {
+                count += 1;
+                sum = (sum.0 + p.0 as f64, sum.1 + p.1 as f64);
+                min = (min.0.min(p.0), min.1.min(p.1));
+                max = (max.0.max(p.0), max.1.max(p.1));
+            }
+            clusters.push(CellCluster {
+                cell,
+                count,
+                centroid: ((sum.0 / count as f64) as f32, (sum.1 / count as f64) as f32),
+                min,
+                max,
+            });
+        }
+        clusters
+    }
+}
@@ -0,0 +1,372 @@
+use std::ops::RangeBounds;
+
+use crate::aggregate::Measure;
+use crate::store::{inclusive, Store};
+
+const MAX_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+enum Node<T, M> {
+    Leaf(Vec<(u64, T)>),
+    Internal(Vec<Child<T, M>>),
+}
+
+#[derive(Debug, Clone)]
+struct Child<T, M> {
+    /// The smallest key in the subtree.
+    min: u64,
+    size: usize,
+    aggregate: M,
+    node: Box<Node<T, M>>,
+}
+
+impl<T, M: Measure<T>> Node<T, M> {
+    fn aggregate(&self) -> M {
+        match self {
+            Node::Leaf(entries) => entries.iter().fold(M::empty(), |a, e| a.combine(&M::measure(&e.1))),
+            Node::Internal(children) => children.iter().fold(M::empty(), |a, c| a.combine(&c.aggregate)),
+        }
+    }
+}
+
+impl<T, M: Measure<T>> Child<T, M> {
+    fn new(node: Node<T, M>) -> Self {
+        let (min, size) = match &node {
+            Node::Leaf(entries) => (entries[0].0, entries.len()),
+            Node::Internal(children) => (children[0].min, children.iter().map(|c| c.size).sum()),
+        };
+        Child { min, size, aggregate: node.aggregate(), node: Box::new(node) }
+    }
+}
+
+/// Index of the child whose keys may include the first one not below `key`.
+fn child_index<T, M>(children: &[Child<T, M>], key: u64) -> usize {
+    children.partition_point(|c| c.min < key).saturating_sub(1)
+}
+
+/// An ordered map where every node knows the size and the aggregate `M` of its subtrees,
+/// so ranks, range counts and range aggregates take O(log n) instead of a scan.
+/// It is a B+ tree, with the same flat, wide nodes that make `BTreeMap` fast.
+#[derive(Debug, Clone)]
+pub struct AugmentedMap<T, M = ()> {
+    root: Node<T, M>,
+    len: usize,
+}
+
+/// An `AugmentedMap` that only keeps subtree sizes.
+pub type CountedMap<T> = AugmentedMap<T, ()>;
+
+impl<T, M: Measure<T>> AugmentedMap<T, M> {
+    pub fn new() -> Self {
+        AugmentedMap { root: Node::Leaf(Vec::new()), len: 0 }
+    }
+
+    pub fn get(&self, key: u64) -> Option<&T> {
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let i = entries.binary_search_by_key(&key, |e| e.0).ok()?;
+                    return Some(&entries[i].1);
+                }
+                Node::Internal(children) => {
+                    node = &children[children.partition_point(|c| c.min <= key).saturating_sub(1)].node;
+                }
+            }
+        }
+    }
+
+    /// Number of keys strictly smaller than `key`.
+    pub fn rank(&self, key: u64) -> usize {
+        let mut rank = 0;
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => return rank + entries.partition_point(|e| e.0 < key),
+                Node::Internal(children) => {
+                    let i = child_index(children, key);
+                    rank += children[..i].iter().map(|c| c.size).sum::<usize>();
+                    node = &children[i].node;
+                }
+            }
+        }
+    }
+
+    /// The entry with `i` keys before it.
+    pub fn select(&self, mut i: usize) -> Option<(&u64, &T)> {
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => return entries.get(i).map(|(k, v)| (k, v)),
+                Node::Internal(children) => {
+                    let child = children.iter().find(|c| {
+                        let found = i < c.size;
+                        if !found { i -= c.size };
+                        found
+                    })?;
+                    node = &child.node;
+                }
+            }
+        }
+    }
+
+    /// Combines the aggregates of every entry in `range`.
+    pub fn aggregate<R: RangeBounds<u64>>(&self, range: R) -> M {
+        let mut aggregate = M::empty();
+        if let Some((start, end)) = inclusive(&range) {
+            Self::aggregate_at(&self.root, start, end, None, &mut aggregate);
+        }
+        aggregate
+    }
+
+    /// Every key in `node` is below `upper`, when there is one.
+    fn aggregate_at(node: &Node<T, M>, start: u64, end: u64, upper: Option<u64>, aggregate: &mut M) {
+        match node {
+            Node::Leaf(entries) => {
+                let from = entries.partition_point(|e| e.0 < start);
+                for e in entries[from..].iter().take_while(|e| e.0 <= end) {
+                    *aggregate = aggregate.combine(&M::measure(&e.1));
+                }
+            }
+            Node::Internal(children) => {
+                for (i, child) in children.iter().enumerate().skip(child_index(children, start)) {
+                    if child.min > end { break };
+                    let next = children.get(i + 1).map(|c| c.min).or(upper);
+                    let whole = start <= child.min && match next {
+                        Some(next) => next - 1 <= end,
+                        None => end == u64::MAX,
+                    };
+                    if whole {
+                        *aggregate = aggregate.combine(&child.aggregate);
+                    } else {
+                        Self::aggregate_at(&child.node, start, end, next, aggregate);
+                    }
+                }
+            }
+        }
+    }
+
+    fn insert_at(node: &mut Node<T, M>, key: u64, value: T) -> (Option<T>, Option<Child<T, M>>) {
+        match node {
+            Node::Leaf(entries) => {
+                match entries.binary_search_by_key(&key, |e| e.0) {
+                    Ok(i) => return (Some(std::mem::replace(&mut entries[i].1, value)), None),
+                    Err(i) => entries.insert(i, (key, value)),
+                }
+                if entries.len() <= MAX_LEN {
+                    return (None, None);
+                }
+                let split = entries.split_off(entries.len() / 2);
+                (None, Some(Child::new(Node::Leaf(split))))
+            }
+            Node::Internal(children) => {
+                let i = children.partition_point(|c| c.min <= key).saturating_sub(1);
+                let child = &mut children[i];
+                let (old, split) = Self::insert_at(&mut child.node, key, value);
+                child.aggregate = child.node.aggregate();
+                if old.is_some() {
+                    return (old, None);
+                }
+                child.size += 1;
+                child.min = child.min.min(key);
+                if let Some(split) = split {
+                    child.size -= split.size;
+                    children.insert(i + 1, split);
+                }
+                if children.len() <= MAX_LEN {
+                    return (None, None);
+                }
+                let split = children.split_off(children.len() / 2);
+                (None, Some(Child::new(Node::Internal(split))))
+            }
+        }
+    }
+}
+
+impl<T, M: Measure<T>> Default for AugmentedMap<T, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A position between two entries: the path down the internal nodes and the leaf.
+struct Cursor<'a, T, M> {
+    path: Vec<(&'a [Child<T, M>], usize)>,
+    leaf: &'a [(u64, T)],
+    index: usize,
+}
+
+impl<T, M> Clone for Cursor<'_, T, M> {
+    fn clone(&self) -> Self {
+        Cursor { path: self.path.clone(), leaf: self.leaf, index: self.index }
+    }
+}
+
+impl<'a, T, M> Cursor<'a, T, M> {
+    /// Placed before the first key not below `key`, or after the last one up to it.
+    fn seek(mut node: &'a Node<T, M>, key: u64, after: bool) -> Self {
+        let mut path = Vec::new();
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let index = match after {
+                        false => entries.partition_point(|e| e.0 < key),
+                        true => entries.partition_point(|e| e.0 <= key),
+                    };
+                    return Cursor { path, leaf: entries, index };
+                }
+                Node::Internal(children) => {
+                    let i = match after {
+                        false => child_index(children, key),
+                        true => children.partition_point(|c| c.min <= key).saturating_sub(1),
+                    };
+                    path.push((children.as_slice(), i));
+                    node = &children[i].node;
+                }
+            }
+        }
+    }
+
+    fn descend(&mut self, mut node: &'a Node<T, M>, first: bool) {
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    self.leaf = entries;
+                    self.index = if first { 0 } else { entries.len() };
+                    return;
+                }
+                Node::Internal(children) => {
+                    let i = if first { 0 } else { children.len() - 1 };
+                    self.path.push((children.as_slice(), i));
+                    node = &children[i].node;
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) -> &'a (u64, T) {
+        while self.index == self.leaf.len() {
+            let (children, i) = loop {
+                let (children, i) = self.path.pop().expect("cursor moved past the end");
+                if i + 1 < children.len() { break (children, i + 1) };
+            };
+            self.path.push((children, i));
+            self.descend(&children[i].node, true);
+        }
+        self.index += 1;
+        &self.leaf[self.index - 1]
+    }
+
+    fn next_back(&mut self) -> &'a (u64, T) {
+        while self.index == 0 {
+            let (children, i) = loop {
+                let (children, i) = self.path.pop().expect("cursor moved past the start");
+                if i > 0 { break (children, i - 1) };
+            };
+            self.path.push((children, i));
+            self.descend(&children[i].node, false);
+        }
+        self.index -= 1;
+        &self.leaf[self.index]
+    }
+}
+
+pub struct Range<'a, T, M = ()> {
+    front: Cursor<'a, T, M>,
+    back: Cursor<'a, T, M>,
+    remaining: usize,
+}
+
+impl<T, M> Clone for Range<'_, T, M> {
+    fn clone(&self) -> Self {
+        Range { front: self.front.clone(), back: self.back.clone(), remaining: self.remaining }
+    }
+}
+
+impl<'a, T, M> Iterator for Range<'a, T, M> {
+    type Item = (&'a u64, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (k, v) = self.front.next();
+        Some((k, v))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, M> DoubleEndedIterator for Range<'_, T, M> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (k, v) = self.back.next_back();
+        Some((k, v))
+    }
+}
+
+impl<T, M> ExactSizeIterator for Range<'_, T, M> {}
+
+impl<T, M: Measure<T>> Store<T> for AugmentedMap<T, M> {
+    type Range<'a> = Range<'a, T, M> where T: 'a, M: 'a;
+
+    fn insert(&mut self, key: u64, value: T) -> Option<T> {
+        let (old, split) = Self::insert_at(&mut self.root, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        if let Some(split) = split {
+            let root = std::mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+            self.root = Node::Internal(vec![Child::new(root), split]);
+        }
+        old
+    }
+
+    fn range<R: RangeBounds<u64>>(&self, range: R) -> Self::Range<'_> {
+        let (start, end, remaining) = match inclusive(&range) {
+            Some((start, end)) => (start, end, self.count(start ..= end)),
+            None => (0, 0, 0),
+        };
+        Range {
+            front: Cursor::seek(&self.root, start, false),
+            back: Cursor::seek(&self.root, end, true),
+            remaining,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn count<R: RangeBounds<u64>>(&self, range: R) -> usize {
+        let Some((start, end)) = inclusive(&range) else { return 0 };
+        match end.checked_add(1) {
+            Some(end) => self.rank(end) - self.rank(start),
+            None => self.len - self.rank(start),
+        }
+    }
+
+    fn count_at_most<R: RangeBounds<u64>>(&self, range: R, limit: usize) -> usize {
+        self.count(range).min(limit)
+    }
+
+    fn rank(&self, key: u64) -> usize {
+        AugmentedMap::rank(self, key)
+    }
+
+    fn select(&self, i: usize) -> Option<(&u64, &T)> {
+        AugmentedMap::select(self, i)
+    }
+
+    fn select_keys(&self, ranks: &[usize]) -> Vec<Option<u64>> {
+        ranks.iter().map(|&i| AugmentedMap::select(self, i).map(|(k, _)| *k)).collect()
+    }
+
+    fn upper_bound<R: RangeBounds<u64>>(&self, range: R) -> Option<f64> {
+        self.aggregate(range).upper_bound()
+    }
+}
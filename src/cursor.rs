@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use crate::{Entry, SpaceFillingCurve, Store, ZOrder};
+
+/// The Morton key of the last point handed out by a [`QueryCursor`].
+/// Resuming from it continues right after that key, so points inserted
+/// between pages are picked up if they fall after it, and nothing is repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueryToken(pub u64);
+
+impl fmt::Display for QueryToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for QueryToken {
+    type Err = ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(QueryToken)
+    }
+}
+
+pub struct QueryCursor<'a, V: 'a = (), S: Store<Entry<V>> + 'a = BTreeMap<u64, Entry<V>>, C: SpaceFillingCurve = ZOrder> {
+    tree: &'a S,
+    zi: C::Box,
+    cursor: S::Range<'a>,
+    missed: usize,
+    last: Option<u64>,
+}
+
+impl<'a, V: 'a, S: Store<Entry<V>> + 'a, C: SpaceFillingCurve> QueryCursor<'a, V, S, C> {
+    pub(crate) fn new(tree: &'a S, zi: C::Box, last: Option<u64>) -> Self {
+        let (min, max) = C::key_range(&zi);
+        let start = match last {
+            None => Some(min),
+            Some(k) => C::next_in_box(&zi, k),
+        };
+        let cursor = match start {
+            Some(k) => tree.range(k ..= max),
+            None => tree.range(max .. max),
+        };
+        QueryCursor { tree, zi, cursor, missed: 0, last }
+    }
+
+    pub fn token(&self) -> Option<QueryToken> {
+        self.last.map(QueryToken)
+    }
+}
+
+impl<'a, V: 'a, S: Store<Entry<V>> + 'a, C: SpaceFillingCurve> Iterator for QueryCursor<'a, V, S, C> {
+    type Item = (&'a (f32, f32), &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let max = C::key_range(&self.zi).1;
+        while let Some((k, (p, v))) = self.cursor.next() {
+            if !C::contains(&self.zi, *k) {
+                self.missed += 1;
+                if self.missed < 32 { continue };
+                let Some(k) = C::next_in_box(&self.zi, *k) else { break };
+                self.cursor = self.tree.range(k ..= max);
+            } else {
+                self.missed = 0;
+                self.last = Some(*k);
+                return Some((p, v))
+            }
+        }
+        None
+    }
+}
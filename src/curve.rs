@@ -0,0 +1,92 @@
+use crate::{hilbert_2, hilbert_reverse_2, morton_2, morton_reverse_2, Cell, HilbertIndexer, InvertedBounds, ZOrderIndexer};
+
+/// The order a `QuadTree` keeps its points in: a map from the grid to 64 bit keys,
+/// and how to walk the keys of a box without visiting the ones outside.
+///
+/// Every key range of a `Cell` must cover a rectangle of the grid, as with
+/// Z-order and Hilbert keys, since the range methods read whole cells at once.
+pub trait SpaceFillingCurve {
+    /// A box of the grid, with whatever the curve precomputes to test keys against it.
+    type Box;
+
+    fn encode(x: u32, y: u32) -> u64;
+    fn decode(key: u64) -> (u32, u32);
+    fn new_box(min: (u32, u32), max: (u32, u32)) -> Self::Box;
+    /// Like `new_box`, but a box inverted along x (axis 0) or y (axis 1) is an error.
+    fn try_new_box(min: (u32, u32), max: (u32, u32)) -> Result<Self::Box, InvertedBounds> {
+        match (min.0 > max.0, min.1 > max.1) {
+            (true, _) => Err(InvertedBounds { axis: 0 }),
+            (_, true) => Err(InvertedBounds { axis: 1 }),
+            _ => Ok(Self::new_box(min, max)),
+        }
+    }
+    /// The first and last keys inside the box.
+    fn key_range(b: &Self::Box) -> (u64, u64);
+    fn contains(b: &Self::Box, key: u64) -> bool;
+    /// The smallest key after `key` inside the box.
+    fn next_in_box(b: &Self::Box, key: u64) -> Option<u64>;
+    /// The grid rectangle covered by the keys of `cell`.
+    fn cell_bounds(cell: &Cell) -> ((u32, u32), (u32, u32));
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZOrder;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hilbert;
+
+impl SpaceFillingCurve for ZOrder {
+    type Box = ZOrderIndexer<2>;
+    fn encode(x: u32, y: u32) -> u64 {
+        morton_2(x, y)
+    }
+    fn decode(key: u64) -> (u32, u32) {
+        morton_reverse_2(key)
+    }
+    fn new_box(min: (u32, u32), max: (u32, u32)) -> Self::Box {
+        ZOrderIndexer::<2>::new(min, max)
+    }
+    fn try_new_box(min: (u32, u32), max: (u32, u32)) -> Result<Self::Box, InvertedBounds> {
+        ZOrderIndexer::<2>::try_new(min, max)
+    }
+    fn key_range(b: &Self::Box) -> (u64, u64) {
+        *b.bounds()
+    }
+    fn contains(b: &Self::Box, key: u64) -> bool {
+        b.contains(key)
+    }
+    fn next_in_box(b: &Self::Box, key: u64) -> Option<u64> {
+        b.next_zorder_index(key)
+    }
+    fn cell_bounds(cell: &Cell) -> ((u32, u32), (u32, u32)) {
+        cell.grid_bounds()
+    }
+}
+
+impl SpaceFillingCurve for Hilbert {
+    type Box = HilbertIndexer<2>;
+    fn encode(x: u32, y: u32) -> u64 {
+        hilbert_2(x, y)
+    }
+    fn decode(key: u64) -> (u32, u32) {
+        hilbert_reverse_2(key)
+    }
+    fn new_box(min: (u32, u32), max: (u32, u32)) -> Self::Box {
+        HilbertIndexer::<2>::new(min, max)
+    }
+    fn key_range(b: &Self::Box) -> (u64, u64) {
+        *b.bounds()
+    }
+    fn contains(b: &Self::Box, key: u64) -> bool {
+        b.contains(key)
+    }
+    fn next_in_box(b: &Self::Box, key: u64) -> Option<u64> {
+        b.next_hilbert_index(key)
+    }
+    fn cell_bounds(cell: &Cell) -> ((u32, u32), (u32, u32)) {
+        // The cell is a square, with one of its corners at the first key
+        let side = u32::MAX.checked_shr(cell.level()).unwrap_or(0);
+        let (x, y) = hilbert_reverse_2(cell.key_range().0);
+        ((x & !side, y & !side), (x | side, y | side))
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{morton_2, morton_4, morton_reverse_2, morton_reverse_4};
+
+// Skilling's transform between coordinates and the "transposed" Hilbert index,
+// whose bits interleave like a Morton key.
+fn axes_to_transpose<const D: usize>(x: &mut [u64; D], bits: u32) {
+    let m = 1 << (bits - 1);
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..D {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+    for i in 1..D {
+        x[i] ^= x[i - 1];
+    }
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[D - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for v in x.iter_mut() {
+        *v ^= t;
+    }
+}
+
+fn transpose_to_axes<const D: usize>(x: &mut [u64; D], bits: u32) {
+    let n = 2 << (bits - 1);
+    let t = x[D - 1] >> 1;
+    for i in (1..D).rev() {
+        x[i] ^= x[i - 1];
+    }
+    x[0] ^= t;
+    let mut q = 2;
+    while q != n {
+        let p = q - 1;
+        for i in (0..D).rev() {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q <<= 1;
+    }
+}
+
+// In 2D the quadrant order only depends on whether the sub-square is transposed
+// and whether it is mirrored, four states, so keys are translated a byte of the
+// Morton key, four levels, at a time.
+const fn hilbert_table(decode: bool) -> [[u16; 256]; 4] {
+    let mut table = [[0; 256]; 4];
+    let mut state = 0;
+    while state < 4 {
+        let mut byte = 0;
+        while byte < 256 {
+            let (mut s, mut out, mut level) = (state, 0, 0);
+            while level < 4 {
+                let pair = (byte >> (6 - 2 * level)) & 3;
+                let (rx, ry) = if decode {
+                    // Digits 0, 1, 2, 3 are the quadrants (0, 0), (0, 1), (1, 1), (1, 0)
+                    let (rx, ry) = ((pair >> 1) & 1, (pair ^ pair >> 1) & 1);
+                    let (x, y) = if s & 1 == 1 { (ry, rx) } else { (rx, ry) };
+                    let (x, y) = if s & 2 == 2 { (x ^ 1, y ^ 1) } else { (x, y) };
+                    out = out << 2 | x << 1 | y;
+                    (rx, ry)
+                } else {
+                    let (x, y) = ((pair >> 1) & 1, pair & 1);
+                    let (x, y) = if s & 1 == 1 { (y, x) } else { (x, y) };
+                    let (rx, ry) = if s & 2 == 2 { (x ^ 1, y ^ 1) } else { (x, y) };
+                    out = out << 2 | (3 * rx) ^ ry;
+                    (rx, ry)
+                };
+                if ry == 0 {
+                    s ^= 1 | (rx << 1);
+                }
+                level += 1;
+            }
+            table[state][byte] = (out | s << 8) as u16;
+            byte += 1;
+        }
+        state += 1;
+    }
+    table
+}
+
+const ENCODE_2: [[u16; 256]; 4] = hilbert_table(false);
+const DECODE_2: [[u16; 256]; 4] = hilbert_table(true);
+
+fn translate_2(key: u64, table: &[[u16; 256]; 4]) -> u64 {
+    let mut state = 0;
+    let mut out = 0;
+    for shift in (0..64).step_by(8).rev() {
+        let entry = table[state][(key >> shift) as usize & 0xFF];
+        out = out << 8 | (entry & 0xFF) as u64;
+        state = (entry >> 8) as usize;
+    }
+    out
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for [u32; 2] {}
+    impl Sealed for [u32; 4] {}
+}
+
+/// The points Hilbert keys are defined for: two axes of 32 bits, or four of 16.
+pub trait HilbertPoint: sealed::Sealed + Copy {
+    fn hilbert_key(self) -> u64;
+    fn from_hilbert_key(key: u64) -> Self;
+}
+
+impl HilbertPoint for [u32; 2] {
+    fn hilbert_key(self) -> u64 {
+        hilbert_2(self[0], self[1])
+    }
+    fn from_hilbert_key(key: u64) -> Self {
+        let (x, y) = hilbert_reverse_2(key);
+        [x, y]
+    }
+}
+
+impl HilbertPoint for [u32; 4] {
+    fn hilbert_key(self) -> u64 {
+        let mut x = self.map(u64::from);
+        axes_to_transpose(&mut x, 16);
+        morton_4(x[0] as u16, x[1] as u16, x[2] as u16, x[3] as u16)
+    }
+    fn from_hilbert_key(key: u64) -> Self {
+        let (a, b, c, d) = morton_reverse_4(key);
+        let mut x = [a, b, c, d].map(u64::from);
+        transpose_to_axes(&mut x, 16);
+        x.map(|v| v as u32)
+    }
+}
+
+pub fn hilbert_2(x: u32, y: u32) -> u64 {
+    translate_2(morton_2(x, y), &ENCODE_2)
+}
+
+pub fn hilbert_reverse_2(h: u64) -> (u32, u32) {
+    morton_reverse_2(translate_2(h, &DECODE_2))
+}
+
+pub fn hilbert_4(x: u16, y: u16, z: u16, w: u16) -> u64 {
+    [x, y, z, w].map(u32::from).hilbert_key()
+}
+
+pub fn hilbert_reverse_4(h: u64) -> (u16, u16, u16, u16) {
+    let [x, y, z, w] = <[u32; 4]>::from_hilbert_key(h);
+    (x as u16, y as u16, z as u16, w as u16)
+}
+
+/// The Hilbert keys of the points inside a box, the counterpart of `ZOrderIndexer`.
+/// Hilbert keys sharing a prefix fill an aligned cube, like Morton keys, but the
+/// order of the children changes from cell to cell, so the box is searched cell by cell.
+pub struct HilbertIndexer<const D: usize> {
+    min: [u32; D],
+    max: [u32; D],
+    bounds: (u64, u64),
+}
+
+impl HilbertIndexer<2> {
+    pub fn new(min: (u32, u32), max: (u32, u32)) -> Self {
+        Self::from_box([min.0, min.1], [max.0, max.1])
+    }
+}
+
+impl HilbertIndexer<4> {
+    pub fn new(min: ((u16, u16), (u16, u16)), max: ((u16, u16), (u16, u16))) -> Self {
+        let min = [min.0.0, min.0.1, min.1.0, min.1.1].map(u32::from);
+        let max = [max.0.0, max.0.1, max.1.0, max.1.1].map(u32::from);
+        Self::from_box(min, max)
+    }
+}
+
+impl<const D: usize> HilbertIndexer<D>
+where
+    [u32; D]: HilbertPoint,
+{
+    const BITS: u32 = 64 / D as u32;
+
+    pub fn from_box(min: [u32; D], max: [u32; D]) -> Self {
+        assert!((0..D).all(|i| min[i] <= max[i]));
+        let mut indexer = HilbertIndexer { min, max, bounds: (0, 0) };
+        let first = indexer.search(0, 0, 0).expect("a box holds at least one point");
+        indexer.bounds = (first, indexer.last(0, 0));
+        indexer
+    }
+
+    pub fn bounds(&self) -> &(u64, u64) {
+        &self.bounds
+    }
+
+    pub fn contains(&self, h: u64) -> bool {
+        let p = <[u32; D]>::from_hilbert_key(h);
+        (0..D).all(|i| self.min[i] <= p[i] && p[i] <= self.max[i])
+    }
+
+    /// The smallest key after `h` whose point is inside the box.
+    pub fn next_hilbert_index(&self, h: u64) -> Option<u64> {
+        let next = h.checked_add(1)?;
+        if next > self.bounds.1 {
+            return None;
+        }
+        if self.contains(next) {
+            return Some(next);
+        }
+        self.search(0, 0, next)
+    }
+
+    /// The keys of a cell, and whether it is inside, across or outside the box.
+    fn cell(&self, prefix: u64, level: u32) -> ((u64, u64), Option<bool>) {
+        let below = (Self::BITS - level) * D as u32;
+        let start = prefix.checked_shl(below).unwrap_or(0);
+        let end = start | 1u64.checked_shl(below).unwrap_or(0).wrapping_sub(1);
+        let side = 1u32.checked_shl(Self::BITS - level).unwrap_or(0).wrapping_sub(1);
+        let lo = <[u32; D]>::from_hilbert_key(start).map(|v| v & !side);
+        let disjoint = (0..D).any(|i| lo[i] | side < self.min[i] || lo[i] > self.max[i]);
+        let inside = (0..D).all(|i| self.min[i] <= lo[i] && lo[i] | side <= self.max[i]);
+        ((start, end), (!disjoint).then_some(inside))
+    }
+
+    /// The smallest key from `target` on in the box, within the given cell.
+    fn search(&self, prefix: u64, level: u32, target: u64) -> Option<u64> {
+        let ((start, end), inside) = self.cell(prefix, level);
+        if end < target {
+            return None;
+        }
+        match inside? {
+            true => Some(start.max(target)),
+            false => (0..1 << D).find_map(|child| self.search(prefix << D | child, level + 1, target)),
+        }
+    }
+
+    /// The largest key in the box, within the given cell.
+    fn last(&self, prefix: u64, level: u32) -> u64 {
+        let ((_, end), inside) = self.cell(prefix, level);
+        if inside == Some(true) {
+            return end;
+        }
+        let child = (0..1 << D).rev()
+            .find(|&child| self.cell(prefix << D | child, level + 1).1.is_some())
+            .expect("the cell overlaps the box");
+        self.last(prefix << D | child, level + 1)
+    }
+}
@@ -0,0 +1,69 @@
+use std::cmp::Reverse;
+
+use crate::{ordered_float, Entry, QuadTree, Rect, SpaceFillingCurve, Store, Visit, Walk};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub rect: Rect,
+    pub cols: usize,
+    pub rows: usize,
+    /// Row major, `rows * cols` bins.
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn new(rect: Rect, cols: usize, rows: usize) -> Self {
+        Histogram { rect, cols, rows, counts: vec![0; cols * rows] }
+    }
+
+    /// The column and row of the bin holding `point`, if it is inside the rectangle.
+    pub fn bin(&self, point: &(f32, f32)) -> Option<(usize, usize)> {
+        let ((x0, y0), (x1, y1)) = self.rect;
+        let (x, y) = (ordered_float(point.0), ordered_float(point.1));
+        if x < ordered_float(x0) || x > ordered_float(x1) || y < ordered_float(y0) || y > ordered_float(y1) {
+            return None;
+        }
+        let col = ((point.0 - x0) / (x1 - x0) * self.cols as f32) as usize;
+        let row = ((point.1 - y0) / (y1 - y0) * self.rows as f32) as usize;
+        Some((col.min(self.cols - 1), row.min(self.rows - 1)))
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> usize {
+        self.counts[row * self.cols + col]
+    }
+
+    pub fn to_2d(&self) -> Vec<Vec<usize>> {
+        self.counts.chunks(self.cols.max(1)).map(|row| row.to_vec()).collect()
+    }
+}
+
+impl<V, S: Store<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, S, C> {
+    /// Counts the points inside `rect` on a `cols` by `rows` grid.
+    /// Quadtree cells that fall within a single bin are counted as one run of keys,
+    /// only cells straddling bins are split, down to a handful of points.
+    pub fn histogram(&self, rect: Rect, cols: usize, rows: usize) -> Histogram {
+        let mut histogram = Histogram::new(rect, cols, rows);
+        if cols == 0 || rows == 0 {
+            return histogram;
+        }
+        self.walk_cells(rect, |cell| Some(Reverse(cell.key_range().0)), |visit| match visit {
+            Visit::Cell { cell, inside: true, .. } => {
+                let (start, end) = cell.key_range();
+                let (lo, hi) = C::cell_bounds(cell);
+                let (first, last) = self.world_rect(lo, hi);
+                let (first, last) = (histogram.bin(&first), histogram.bin(&last));
+                let Some((col, row)) = first.filter(|_| first == last) else { return Walk::Descend };
+                histogram.counts[row * cols + col] += self.tree.count(start ..= end);
+                Walk::Skip
+            }
+            Visit::Cell { .. } => Walk::Descend,
+            Visit::Entry((p, _)) => {
+                if let Some((col, row)) = histogram.bin(p) {
+                    histogram.counts[row * cols + col] += 1;
+                }
+                Walk::Descend
+            }
+        });
+        histogram
+    }
+}
@@ -0,0 +1,209 @@
+use std::cmp::Reverse;
+use std::iter::Peekable;
+
+use crate::{Cell, Entry, QuadTree, SpaceFillingCurve, Store, AABB};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Square neighbourhoods, the same as an `AABB`.
+    Chebyshev,
+    /// Circular neighbourhoods.
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(&self, a: &(f32, f32), b: &(f32, f32)) -> f32 {
+        let (dx, dy) = ((a.0 - b.0).abs(), (a.1 - b.1).abs());
+        match self {
+            Metric::Chebyshev => dx.max(dy),
+            Metric::Euclidean => dx.hypot(dy),
+        }
+    }
+    pub fn within(&self, a: &(f32, f32), b: &(f32, f32), distance: f32) -> bool {
+        match self {
+            Metric::Chebyshev => AABB(*a, distance).contains(b),
+            Metric::Euclidean => self.distance(a, b) <= distance,
+        }
+    }
+}
+
+fn square_dist(a: (u32, u32), b: (u32, u32)) -> u32 {
+    u32::max(u32::abs_diff(a.0, b.0), u32::abs_diff(a.1, b.1))
+}
+
+/// For every point of `a`, in curve order, the `k` points of `b` nearest to it,
+/// with the same distance and ordering as `QuadTree::nearest`.
+/// The k-th distance of one query point bounds the search box of the next,
+/// so consecutive points close in curve only need a single range query.
+pub fn knn_join<'a, 'b, V, W, S, T, C, D>(
+    a: &'a QuadTree<V, S, C>,
+    b: &'b QuadTree<W, T, D>,
+    k: usize,
+) -> impl Iterator<Item = (&'a (f32, f32), Vec<&'b (f32, f32)>)>
+where
+    S: Store<Entry<V>>,
+    T: Store<Entry<W>>,
+    C: SpaceFillingCurve,
+    D: SpaceFillingCurve,
+{
+    let mut last: Option<((u32, u32), u32)> = None;
+    let mut candidates: Vec<(u32, &(f32, f32))> = Vec::new();
+    a.iter().map(move |(p, _)| {
+        let q = b.grid_point(*p);
+        let dist = move |p: &(f32, f32)| square_dist(q, b.grid_point(*p));
+        candidates.clear();
+        match last {
+            // The last k neighbours are all within radius + step of this point,
+            // past a few radii a fresh search is cheaper than the enlarged box
+            Some((origin, radius)) if square_dist(origin, q) <= radius.saturating_mul(4) => {
+                let bound = radius.saturating_add(square_dist(origin, q));
+                let min = (q.0.saturating_sub(bound), q.1.saturating_sub(bound));
+                let max = (q.0.saturating_add(bound), q.1.saturating_add(bound));
+                candidates.extend(b.query(min, max).map(|p| (dist(p), p)));
+                if candidates.len() > k {
+                    candidates.select_nth_unstable_by_key(k, |t| t.0);
+                    candidates.truncate(k);
+                }
+                candidates.sort_by_key(|t| t.0);
+            }
+            _ => candidates.extend(b.nearest(*p).take(k).map(|p| (dist(p), p))),
+        }
+        last = match candidates.last() {
+            Some(&(radius, _)) if candidates.len() == k => Some((q, radius)),
+            _ => None,
+        };
+        (p, candidates.iter().map(|t| t.1).collect())
+    })
+}
+
+/// Takes the next run of points, in curve order, that fits in a box of side `2 * eps`.
+/// Returns the bounding box of the run.
+fn next_batch<'a, V, I>(
+    points: &mut Peekable<I>,
+    eps: f32,
+    batch: &mut Vec<(u64, &'a (f32, f32))>,
+) -> Option<((f32, f32), (f32, f32))>
+where
+    V: 'a,
+    I: Iterator<Item = (&'a u64, &'a ((f32, f32), V))>,
+{
+    let (k, (first, _)) = points.next()?;
+    batch.clear();
+    batch.push((*k, first));
+    let (mut min, mut max) = (*first, *first);
+    while batch.len() < 64 {
+        let Some((_, (p, _))) = points.peek() else { break };
+        let lo = (min.0.min(p.0), min.1.min(p.1));
+        let hi = (max.0.max(p.0), max.1.max(p.1));
+        if hi.0 - lo.0 > 2.0 * eps || hi.1 - lo.1 > 2.0 * eps { break };
+        (min, max) = (lo, hi);
+        batch.extend(points.next().map(|(k, (p, _))| (*k, p)));
+    }
+    Some((min, max))
+}
+
+/// All pairs of points from `a` and `b` no farther than `eps` apart, none when
+/// `eps` is negative or NaN. The neighbourhood of each point of `a` is covered by
+/// at most four cells of `b`, which are runs of its keys. Sorted by their first
+/// key, the cells are merged with the keys of `b` in a single pass, which seeks
+/// over the stretches of `b` that no cell reaches. Pairs come in the order of `b`.
+pub fn within_distance_join<'a, 'b, V, W, S, T, C, D>(
+    a: &'a QuadTree<V, S, C>,
+    b: &'b QuadTree<W, T, D>,
+    eps: f32,
+    metric: Metric,
+) -> impl Iterator<Item = (&'a (f32, f32), &'b (f32, f32))>
+where
+    S: Store<Entry<V>>,
+    T: Store<Entry<W>>,
+    C: SpaceFillingCurve,
+    D: SpaceFillingCurve,
+{
+    let valid = !eps.is_nan() && eps >= 0.0;
+    let mut cells: Vec<(u64, u64, &'a (f32, f32))> = Vec::new();
+    for (p, _) in a.iter().filter(|_| valid) {
+        let Some((min, max)) = b.grid_rect(((p.0 - eps, p.1 - eps), (p.0 + eps, p.1 + eps))) else { continue };
+        // Cells at this level are wider than the box, so it spans at most two a side
+        let level = u32::max(max.0 - min.0, max.1 - min.1).leading_zeros();
+        let mut corners = [(min.0, min.1), (max.0, min.1), (min.0, max.1), (max.0, max.1)]
+            .map(|(x, y)| Cell::from_key(D::encode(x, y), level).key_range());
+        corners.sort_unstable();
+        for (i, &(start, end)) in corners.iter().enumerate() {
+            if i == 0 || corners[i - 1] != (start, end) {
+                cells.push((start, end, p));
+            }
+        }
+    }
+    cells.sort_unstable_by_key(|&(start, end, _)| (start, Reverse(end)));
+
+    let mut points = b.tree.range(cells.first().map_or(u64::MAX, |c| c.0)..);
+    let mut next = 0;
+    let mut active: Vec<(u64, u64, &(f32, f32))> = Vec::new();
+    let mut pairs = Vec::new();
+    std::iter::from_fn(move || loop {
+        if let Some(pair) = pairs.pop() {
+            return Some(pair);
+        }
+        let (k, (q, _)) = points.next()?;
+        // Cells are nested or disjoint, so the innermost active one ends first
+        while active.last().is_some_and(|c| c.1 < *k) {
+            active.pop();
+        }
+        while let Some(&c) = cells.get(next).filter(|c| c.0 <= *k) {
+            next += 1;
+            if c.1 >= *k { active.push(c) };
+        }
+        if active.is_empty() {
+            let &(start, _, _) = cells.get(next)?;
+            points = b.tree.range(start..);
+            continue;
+        }
+        pairs.extend(active.iter().filter(|c| metric.within(c.2, q, eps)).map(|c| (c.2, q)));
+    })
+}
+
+impl<V, S: Store<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, S, C> {
+    /// The result of `count_within_distance` for every point, in curve order,
+    /// all zero when `distance` is negative or NaN.
+    /// Points close in curve are batched to share a single range query.
+    pub fn neighbor_counts(&self, distance: f32) -> Vec<usize> {
+        if distance.is_nan() || distance < 0.0 {
+            return vec![0; self.len()];
+        }
+        let mut counts = Vec::with_capacity(self.len());
+        let mut points = self.tree.range(..).peekable();
+        let mut batch = Vec::new();
+        while let Some((min, max)) = next_batch(&mut points, distance, &mut batch) {
+            let offset = counts.len();
+            counts.resize(offset + batch.len(), 0);
+            for q in self.query_float((min.0 - distance, min.1 - distance), (max.0 + distance, max.1 + distance)) {
+                for (count, (_, p)) in counts[offset..].iter_mut().zip(&batch) {
+                    if AABB(**p, distance).contains(q) { *count += 1 };
+                }
+            }
+        }
+        counts
+    }
+
+    /// Every unordered pair of distinct points within `distance` of each other,
+    /// using the same square neighbourhood as `count_within_distance`,
+    /// none when `distance` is negative or NaN.
+    pub fn all_pairs_within(&self, distance: f32) -> impl Iterator<Item = (&(f32, f32), &(f32, f32))> {
+        let mut points = self.tree.range(..).peekable();
+        let mut batch = Vec::new();
+        let mut pairs = Vec::new();
+        let valid = !distance.is_nan() && distance >= 0.0;
+        std::iter::from_fn(move || loop {
+            if let Some(pair) = pairs.pop() {
+                return Some(pair);
+            }
+            let (min, max) = next_batch(&mut points, distance, &mut batch).filter(|_| valid)?;
+            for q in self.query_float((min.0 - distance, min.1 - distance), (max.0 + distance, max.1 + distance)) {
+                let z = self.key_of(*q).unwrap();
+                pairs.extend(batch.iter()
+                    .filter(|(k, p)| *k < z && AABB(**p, distance).contains(q))
+                    .map(|&(_, p)| (p, q)));
+            }
+        })
+    }
+}
@@ -4,13 +4,44 @@ mod tests;
 mod tests_4d;
 
 mod morton;
+mod schedule;
+mod batch;
+mod hilbert;
+mod curve;
+mod quantizer;
+mod cursor;
+mod join;
+mod cell;
+mod histogram;
+mod store;
+mod counted;
+mod aggregate;
+mod topk;
+pub mod cluster;
 
 pub use morton::*;
+pub use schedule::*;
+pub use batch::*;
+pub use hilbert::*;
+pub use curve::*;
+pub use quantizer::{CellCollision, Quantizer};
+pub use cursor::*;
+pub use join::*;
+pub use cell::*;
+pub use histogram::*;
+pub use store::Store;
+pub use counted::{AugmentedMap, CountedMap};
+pub use aggregate::*;
 
-use std::collections::BTreeMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::marker::PhantomData;
 
+use rand::seq::IteratorRandom;
 use rand::Rng;
 
+use quantizer::SLOT_BITS;
+
 pub fn generate_random_points(num_points: usize, size: f32) -> Vec<(f32, f32)> {
     let mut rng = rand::thread_rng();
     (0..num_points)
@@ -18,11 +49,26 @@ pub fn generate_random_points(num_points: usize, size: f32) -> Vec<(f32, f32)> {
         .collect()
 }
 
+/// A rectangle given by its min and max corners.
+pub type Rect = ((f32, f32), (f32, f32));
+
+/// A point and the value stored with it.
+pub type Entry<V> = ((f32, f32), V);
+
 #[derive(Debug)]
-pub struct QuadTree {
-    tree: BTreeMap<u64, (f32, f32)>,
+pub struct QuadTree<V = (), S = BTreeMap<u64, Entry<V>>, C = ZOrder> {
+    tree: S,
+    /// Maps points to the grid, `ordered_float` when there is none.
+    quantizer: Option<Quantizer>,
+    values: PhantomData<(V, C)>,
 }
 
+/// A `QuadTree` backed by a `CountedMap`, which counts cells without visiting their points.
+pub type CountedQuadTree<V = ()> = QuadTree<V, CountedMap<Entry<V>>>;
+
+/// A `QuadTree` ordering its points along the Hilbert curve.
+pub type HilbertQuadTree<V = ()> = QuadTree<V, BTreeMap<u64, Entry<V>>, Hilbert>;
+
 #[derive(Debug)]
 pub struct AABB((f32, f32), f32);
 impl AABB {
@@ -35,45 +81,271 @@ impl AABB {
     }
 }
 
-impl QuadTree {
+impl<S: Store<Entry<()>>, C: SpaceFillingCurve> QuadTree<(), S, C> {
+    pub fn insert(&mut self, point: (f32, f32)) {
+        self.insert_value(point, ());
+    }
+
+    pub fn try_insert(&mut self, point: (f32, f32)) -> Result<(), CellCollision<()>> {
+        self.try_insert_value(point, ()).map(|_| ())
+    }
+}
+
+impl<V> QuadTree<V> {
     pub fn new() -> Self {
+        Self::with_store(BTreeMap::new())
+    }
+
+    pub fn quantized(quantizer: Quantizer) -> Self {
+        Self::with_quantizer(BTreeMap::new(), quantizer)
+    }
+}
+
+/// The box with the smaller of `min` and `max` on each axis as its minimum.
+fn normalized<T: PartialOrd + Copy>(min: (T, T), max: (T, T)) -> ((T, T), (T, T)) {
+    let order = |a: T, b: T| if b < a { (b, a) } else { (a, b) };
+    let ((x0, x1), (y0, y1)) = (order(min.0, max.0), order(min.1, max.1));
+    ((x0, y0), (x1, y1))
+}
+
+/// The grid coordinates of a point, shared by the tree and the closures it hands out.
+/// A quantized point gets the first slot of its step.
+fn grid_point(quantizer: Option<Quantizer>, point: (f32, f32)) -> (u32, u32) {
+    match quantizer {
+        Some(q) => {
+            let (x, y) = q.quantize(point);
+            (x << SLOT_BITS, y << SLOT_BITS)
+        }
+        None => (ordered_float(point.0), ordered_float(point.1)),
+    }
+}
+
+fn same_point(a: &(f32, f32), b: &(f32, f32)) -> bool {
+    a.0.to_bits() == b.0.to_bits() && a.1.to_bits() == b.1.to_bits()
+}
+
+fn rect_contains(rect: &Rect, point: &(f32, f32)) -> bool {
+    rect.0.0 <= point.0 && point.0 <= rect.1.0 && rect.0.1 <= point.1 && point.1 <= rect.1.1
+}
+
+impl<V, S: Store<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, S, C> {
+    pub fn with_store(tree: S) -> Self {
         QuadTree {
-            tree: BTreeMap::new(),
+            tree,
+            quantizer: None,
+            values: PhantomData,
         }
     }
-    pub fn insert(&mut self, point: (f32, f32)) {
-        let x = ordered_float(point.0);
-        let y = ordered_float(point.1);
-        let z_index = morton_2(x, y);
-        self.tree.insert(z_index, point);
+
+    /// A tree placing points on the grid of `quantizer` rather than by `ordered_float`.
+    /// Points keep their exact coordinates, and rectangle queries test them, but
+    /// `nearest` and the other grid-based searches only see the grid cells.
+    /// Points sharing a grid step are told apart by the low `SLOT_BITS` of their grid
+    /// coordinates, so grid boxes on this tree cover the whole steps of their corners.
+    /// Only when all `2^16` slots of a step are taken does `insert_value` panic,
+    /// and `try_insert_value` hands the point back instead.
+    pub fn with_quantizer(tree: S, quantizer: Quantizer) -> Self {
+        QuadTree {
+            tree,
+            quantizer: Some(quantizer),
+            values: PhantomData,
+        }
     }
 
-    pub fn query(&self, min: (u32, u32), max: (u32, u32)) -> impl Iterator<Item = &(f32, f32)> {
-        let zi = ZOrderIndexer::<2>::new(min, max);
-        let (min, max) = *zi.bounds();
-        let mut cursor = self.tree.range(min ..= max);
-        let mut missed = 0;
-        std::iter::from_fn(move || {
-            loop {
-                let Some((k, p)) = cursor.next() else { break };
-                if !zi.contains(*k) {
-                    missed += 1;
-                    if missed < 32 { continue };
-                    let Some(k) = zi.next_zorder_index(*k) else { break };
-                    cursor = self.tree.range(k ..= max);
-                } else {
-                    missed = 0;
-                    return Some(p)
-                }
+    /// The grid coordinates of `point`, which `query` and `query_cursor` take.
+    pub fn grid_point(&self, point: (f32, f32)) -> (u32, u32) {
+        grid_point(self.quantizer, point)
+    }
+
+    /// The corners of the grid box holding every point of `rect`, `None` when it is empty.
+    fn grid_rect(&self, rect: Rect) -> Option<((u32, u32), (u32, u32))> {
+        let (min, max) = (self.grid_point(rect.0), self.grid_point(rect.1));
+        (min.0 <= max.0 && min.1 <= max.1).then(|| self.grid_slots(min, max))
+    }
+
+    /// The grid box widened to every slot of the steps holding its corners.
+    fn grid_slots(&self, min: (u32, u32), max: (u32, u32)) -> ((u32, u32), (u32, u32)) {
+        match self.quantizer {
+            None => (min, max),
+            Some(_) => {
+                let slots = u32::MAX >> (32 - SLOT_BITS);
+                ((min.0 & !slots, min.1 & !slots), (max.0 | slots, max.1 | slots))
+            }
+        }
+    }
+
+    /// The curve box of the keys whose points have grid coordinates from `min` to `max`.
+    fn key_box(&self, min: (u32, u32), max: (u32, u32)) -> Result<C::Box, InvertedBounds> {
+        let zi = C::try_new_box(min, max)?;
+        Ok(match self.quantizer {
+            None => zi,
+            Some(_) => {
+                let (min, max) = self.grid_slots(min, max);
+                C::new_box(min, max)
             }
-            None
         })
     }
 
+    /// The first and last key a point could be stored under.
+    fn slot_range(&self, point: (f32, f32)) -> (u64, u64) {
+        let (x, y) = self.grid_point(point);
+        let z_index = C::encode(x, y);
+        match self.quantizer {
+            None => (z_index, z_index),
+            Some(_) => Cell::from_key(z_index, Cell::MAX_LEVEL - SLOT_BITS).key_range(),
+        }
+    }
+
+    /// The key `point` is stored under, if it is in the tree.
+    pub(crate) fn key_of(&self, point: (f32, f32)) -> Option<u64> {
+        let (start, end) = self.slot_range(point);
+        self.tree.range(start ..= end).find(|(_, e)| same_point(&e.0, &point)).map(|(k, _)| *k)
+    }
+
+    /// The part of a grid box from `grid_rect` whose points are all inside the rectangle.
+    /// Quantized steps on the border may also hold points outside it.
+    fn grid_inner(&self, (min, max): ((u32, u32), (u32, u32))) -> ((u32, u32), (u32, u32)) {
+        match self.quantizer {
+            None => (min, max),
+            Some(_) => {
+                let step = 1 << SLOT_BITS;
+                (
+                    (min.0.saturating_add(step), min.1.saturating_add(step)),
+                    (max.0.saturating_sub(step), max.1.saturating_sub(step)),
+                )
+            }
+        }
+    }
+
+    /// A world rectangle covering every point in the grid box.
+    fn world_rect(&self, min: (u32, u32), max: (u32, u32)) -> Rect {
+        match self.quantizer {
+            None => (
+                (ordered_float_clamped(min.0), ordered_float_clamped(min.1)),
+                (ordered_float_clamped(max.0), ordered_float_clamped(max.1)),
+            ),
+            // Widened by a step against rounding
+            Some(q) => q.world_rect(
+                ((min.0 >> SLOT_BITS).saturating_sub(1), (min.1 >> SLOT_BITS).saturating_sub(1)),
+                ((max.0 >> SLOT_BITS).saturating_add(1), (max.1 >> SLOT_BITS).saturating_add(1)),
+            ),
+        }
+    }
+
+    pub fn insert_value(&mut self, point: (f32, f32), value: V) -> Option<V> {
+        self.try_insert_value(point, value).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like `insert_value`, but a point landing in a grid step whose slots are all
+    /// taken is returned as an error rather than panicking. Only quantized trees
+    /// put different points in the same step.
+    pub fn try_insert_value(&mut self, point: (f32, f32), value: V) -> Result<Option<V>, CellCollision<V>> {
+        let (start, end) = self.slot_range(point);
+        // The key of the same point if it is there, otherwise the first free slot
+        let mut free = None;
+        let mut next = Some(start);
+        for (k, (p, _)) in self.tree.range(start ..= end) {
+            if same_point(p, &point) {
+                free = Some(*k);
+                break;
+            }
+            if free.is_none() && next != Some(*k) {
+                free = next;
+            }
+            next = k.checked_add(1);
+        }
+        let Some(z_index) = free.or(next.filter(|k| *k <= end)) else {
+            let existing = self.tree.range(start ..= end).next().map(|(_, e)| e.0).unwrap();
+            return Err(CellCollision { point, value, existing });
+        };
+        Ok(self.tree.insert(z_index, (point, value)).map(|(_, v)| v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Number of points whose Morton key is below `key`.
+    pub fn rank(&self, key: u64) -> usize {
+        self.tree.rank(key)
+    }
+
+    /// The Morton key, point and value with `i` points before it in Morton order.
+    pub fn select(&self, i: usize) -> Option<(u64, &(f32, f32), &V)> {
+        self.tree.select(i).map(|(k, e)| (*k, &e.0, &e.1))
+    }
+
+    /// The `parts - 1` Morton keys splitting the tree into `parts` partitions of
+    /// nearly equal size: partition `j` holds the keys from the `j - 1`th split key
+    /// up to, but excluding, the `j`th. Partitions are empty when there are fewer
+    /// points than parts.
+    pub fn quantile_keys(&self, parts: usize) -> Vec<u64> {
+        let len = self.len();
+        let ranks: Vec<usize> = (1..parts).map(|j| len * j / parts).collect();
+        self.tree.select_keys(&ranks).into_iter().map(|k| k.unwrap_or(u64::MAX)).collect()
+    }
+
+    /// All points and their values, in Morton order.
+    pub fn iter(&self) -> impl Iterator<Item = (&(f32, f32), &V)> {
+        self.tree.range(..).map(|(_, e)| (&e.0, &e.1))
+    }
+
+    pub fn query(&self, min: (u32, u32), max: (u32, u32)) -> impl Iterator<Item = &(f32, f32)> {
+        self.query_cursor(min, max).map(|(p, _)| p)
+    }
+
+    /// Like `query`, but a box inverted on some axis is an error instead of a panic.
+    pub fn try_query(&self, min: (u32, u32), max: (u32, u32)) -> Result<impl Iterator<Item = &(f32, f32)>, InvertedBounds> {
+        Ok(self.try_query_cursor(min, max)?.map(|(p, _)| p))
+    }
+
+    /// Like `query`, swapping `min` and `max` on any axis where they are inverted.
+    pub fn query_normalized(&self, min: (u32, u32), max: (u32, u32)) -> impl Iterator<Item = &(f32, f32)> {
+        let (min, max) = normalized(min, max);
+        self.query(min, max)
+    }
+
+    pub fn query_cursor(&self, min: (u32, u32), max: (u32, u32)) -> QueryCursor<'_, V, S, C> {
+        self.try_query_cursor(min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_query_cursor(&self, min: (u32, u32), max: (u32, u32)) -> Result<QueryCursor<'_, V, S, C>, InvertedBounds> {
+        Ok(QueryCursor::new(&self.tree, self.key_box(min, max)?, None))
+    }
+
+    pub fn query_from(&self, token: QueryToken, min: (u32, u32), max: (u32, u32)) -> QueryCursor<'_, V, S, C> {
+        self.try_query_from(token, min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_query_from(&self, token: QueryToken, min: (u32, u32), max: (u32, u32)) -> Result<QueryCursor<'_, V, S, C>, InvertedBounds> {
+        Ok(QueryCursor::new(&self.tree, self.key_box(min, max)?, Some(token.0)))
+    }
+
     pub fn query_float(&self, min: (f32, f32), max: (f32, f32)) -> impl Iterator<Item = &(f32, f32)> {
-        let x = (ordered_float(min.0), ordered_float(min.1));
-        let y = (ordered_float(max.0), ordered_float(max.1));
-        self.query(x, y)
+        self.query_rect((min, max)).map(|(p, _)| p)
+    }
+
+    pub fn try_query_float(&self, min: (f32, f32), max: (f32, f32)) -> Result<impl Iterator<Item = &(f32, f32)>, InvertedBounds> {
+        Ok(self.try_query_rect((min, max))?.map(|(p, _)| p))
+    }
+
+    pub fn query_float_normalized(&self, min: (f32, f32), max: (f32, f32)) -> impl Iterator<Item = &(f32, f32)> {
+        self.query_rect(normalized(min, max)).map(|(p, _)| p)
+    }
+
+    fn query_rect(&self, rect: Rect) -> impl Iterator<Item = (&(f32, f32), &V)> {
+        self.try_query_rect(rect).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// The entries inside `rect`, tested one by one on a quantized grid.
+    fn try_query_rect(&self, rect: Rect) -> Result<impl Iterator<Item = (&(f32, f32), &V)>, InvertedBounds> {
+        let (min, max) = (self.grid_point(rect.0), self.grid_point(rect.1));
+        let exact = self.quantizer.is_none();
+        Ok(self.try_query_cursor(min, max)?.filter(move |(p, _)| exact || rect_contains(&rect, p)))
     }
 
     pub fn query_aabb(&self, range: &AABB) -> impl Iterator<Item = &(f32, f32)> {
@@ -82,24 +354,54 @@ impl QuadTree {
         self.query_float(min, max)
     }
 
+    /// A uniform sample of `n` entries inside `rect`, or all of them if there are fewer.
+    /// It is a reservoir over the query, so only the sample is kept in memory,
+    /// and the same seeded `rng` picks the same entries. The sample is not shuffled.
+    pub fn sample_in_range<R: Rng + ?Sized>(&self, rect: Rect, n: usize, rng: &mut R) -> Vec<(&(f32, f32), &V)> {
+        if self.grid_rect(rect).is_none() {
+            return Vec::new();
+        }
+        self.query_rect(rect).choose_multiple(rng, n)
+    }
+
     pub fn nearest(&self, point: (f32, f32)) -> impl Iterator<Item = &(f32, f32)> {
-        let (x, y) = (ordered_float(point.0), ordered_float(point.1));
+        self.nearest_entries(point, |_, _| true).map(|e| &e.0)
+    }
+
+    /// Like `nearest`, but entries rejected by `filter` are skipped
+    /// before they are counted towards the search rings.
+    pub fn nearest_filtered<F>(&self, point: (f32, f32), filter: F) -> impl Iterator<Item = (&(f32, f32), &V)>
+    where
+        F: Fn(&(f32, f32), &V) -> bool,
+    {
+        self.nearest_entries(point, filter).map(|e| (&e.0, &e.1))
+    }
+
+    fn nearest_entries<F>(&self, point: (f32, f32), filter: F) -> impl Iterator<Item = &((f32, f32), V)>
+    where
+        F: Fn(&(f32, f32), &V) -> bool,
+    {
+        let quantizer = self.quantizer;
+        let (x, y) = grid_point(quantizer, point);
         let square_dist = move |p: (u32, u32)| u32::max(u32::abs_diff(p.0, x), u32::abs_diff(p.1, y));
-        let square_dist = move |p: (f32, f32)| square_dist((ordered_float(p.0), ordered_float(p.1)));
-        let z = morton_2(x, y);
+        let square_dist = move |p: (f32, f32)| square_dist(grid_point(quantizer, p));
+        let z = C::encode(x, y);
         let mut a = self.tree.range(..z).rev()
-            .map(move |(_, p)| (square_dist(*p), p))
+            .map(move |(_, e)| (square_dist(e.0), e))
             .peekable();
         let mut b = self.tree.range(z..)
-            .map(move |(_, p)| (square_dist(*p), p))
+            .map(move |(_, e)| (square_dist(e.0), e))
             .peekable();
         let mut iter = std::iter::from_fn(move || match (a.peek(), b.peek()) {
             (None, None) => None,
             (Some(pa), Some(pb)) => if pa.0 <= pb.0 { a.next() } else { b.next() },
             (Some(_), None) => a.next(),
             (None, Some(_)) => b.next(),
-        });
-        let mut queue: Vec<&(f32, f32)> = (&mut iter).take_while(|t| t.0 == 0).map(|t| t.1).collect();
+        }).peekable();
+        let mut queue: Vec<&((f32, f32), V)> = std::iter::from_fn(|| iter.next_if(|t| t.0 == 0))
+            .map(|t| t.1)
+            .filter(|(p, v)| filter(p, v))
+            .collect();
         let mut keys: Vec<u32> = Vec::new();
         let mut min_dist = 1;
         std::iter::from_fn(move || {
@@ -107,7 +409,10 @@ impl QuadTree {
                 if let Some(p) = queue.pop() {
                     return Some(p);
                 }
-                let (distance, _) = (&mut iter).filter(|(d, _)| *d >= min_dist).take(8).max_by_key(|t| t.0)?;
+                let (distance, _) = (&mut iter)
+                    .filter(|(d, (p, v))| *d >= min_dist && filter(p, v))
+                    .take(8)
+                    .max_by_key(|t| t.0)?;
                 let squares = [
                     (
                         (x.saturating_sub(distance), y.saturating_sub(min_dist + 1)),
@@ -126,37 +431,42 @@ impl QuadTree {
                 ];
                 let mut zis: Vec<_> = squares.into_iter()
                     .filter(|(min, max)| min.0 <= max.0 && min.1 <= max.1)
-                    .map(|(min, max)| ZOrderIndexer::<2>::new(min, max))
+                    .map(|(min, max)| {
+                        let (min, max) = self.grid_slots(min, max);
+                        C::new_box(min, max)
+                    })
                     .collect();
-                let Some(min) = zis.iter().map(|zi| zi.bounds().0).min() else { continue };
-                let Some(max) = zis.iter().map(|zi| zi.bounds().1).max() else { continue };
+                let Some(min) = zis.iter().map(|zi| C::key_range(zi).0).min() else { continue };
+                let Some(max) = zis.iter().map(|zi| C::key_range(zi).1).max() else { continue };
                 let mut cursor = self.tree.range(min ..= max);
                 let mut missed = 0;
                 let mut zi = &zis[0];
-                loop {
-                    let Some((k, p)) = cursor.next() else { break };
-                    if !zi.contains(*k) {
-                        if zis.iter().any(|zi| zi.contains(*k)) {
+                while let Some((k, p)) = cursor.next() {
+                    if !C::contains(zi, *k) {
+                        if zis.iter().any(|zi| C::contains(zi, *k)) {
                             zi = {
-                                zis.retain(|zi| *k <= zi.bounds().1);
-                                zis.iter().find(|zi| zi.contains(*k)).unwrap()
+                                zis.retain(|zi| *k <= C::key_range(zi).1);
+                                zis.iter().find(|zi| C::contains(zi, *k)).unwrap()
                             };
                         } else {
                             missed += 1;
                             if missed < 32 { continue };
                             let Some(k) = zis.iter()
-                                .filter_map(|zi| zi.next_zorder_index(*k))
+                                .filter_map(|zi| C::next_in_box(zi, *k))
                                 .min() else { break };
                             cursor = self.tree.range(k ..= max);
                             continue;
                         };
                     }
                     missed = 0;
-                    queue.push(p);
+                    // Slots widen the boxes into the steps of the last ring
+                    if (quantizer.is_none() || square_dist(p.0) >= min_dist) && filter(&p.0, &p.1) {
+                        queue.push(p);
+                    }
                 }
                 if queue.len() < 64 {
                     keys.clear();
-                    keys.extend(queue.iter().map(|&p| square_dist(*p)));
+                    keys.extend(queue.iter().map(|e| square_dist(e.0)));
                     for i in 1..queue.len() {
                         for j in (0..i).rev() {
                             if keys[j] >= keys[j+1] { break };
@@ -165,21 +475,171 @@ impl QuadTree {
                         }
                     }
                 } else {
-                    queue.sort_by_cached_key(|&p| std::cmp::Reverse(square_dist(*p)));
+                    queue.sort_by_cached_key(|e| std::cmp::Reverse(square_dist(e.0)));
                 }
                 min_dist = distance + 1;
             }
         })
     }
 
+    /// Finds a point at most `1 + epsilon` times farther than the nearest one,
+    /// measured on the grid like `nearest`. The flag tells whether it is the exact nearest.
+    pub fn nearest_approx(&self, point: (f32, f32), epsilon: f32) -> Option<(&(f32, f32), bool)> {
+        self.nearest_bounded(point, epsilon, f32::INFINITY)
+    }
+
+    /// The point `nearest` would return first among those no farther than `max_distance`
+    /// in Chebyshev distance, `None` when there is none.
+    pub fn nearest_within(&self, point: (f32, f32), max_distance: f32) -> Option<(&(f32, f32), bool)> {
+        self.nearest_bounded(point, 0.0, max_distance)
+    }
+
+    fn nearest_bounded(&self, point: (f32, f32), epsilon: f32, max_distance: f32) -> Option<(&(f32, f32), bool)> {
+        if max_distance.is_nan() || max_distance < 0.0 {
+            return None;
+        }
+        let within = move |p: &(f32, f32)| f32::max((p.0 - point.0).abs(), (p.1 - point.1).abs()) <= max_distance;
+        let (x, y) = self.grid_point(point);
+        let grid_dist = |p: &(f32, f32)| {
+            let (px, py) = self.grid_point(*p);
+            u32::max(px.abs_diff(x), py.abs_diff(y))
+        };
+        let z = C::encode(x, y);
+        // Neighbours in curve give an upper bound on the distance
+        let before = self.tree.range(..z).rev().take(8);
+        let after = self.tree.range(z..).take(8);
+        let candidate = before.chain(after)
+            .map(|(_, (p, _))| p)
+            .filter(|p| within(p))
+            .min_by_key(|p| grid_dist(p));
+        // Anything closer than the bound, shrunk by epsilon, must lie in this box
+        let radius = match candidate {
+            Some(p) => (grid_dist(p) as f64 / (1.0 + epsilon.max(0.0) as f64)) as u32,
+            None => u32::MAX,
+        };
+        let reach = ((point.0 - max_distance, point.1 - max_distance), (point.0 + max_distance, point.1 + max_distance));
+        let (lo, hi) = self.grid_rect(reach)?;
+        let min = (x.saturating_sub(radius).max(lo.0), y.saturating_sub(radius).max(lo.1));
+        let max = (x.saturating_add(radius).min(hi.0), y.saturating_add(radius).min(hi.1));
+        let found = (min.0 <= max.0 && min.1 <= max.1)
+            .then(|| self.query(min, max).filter(|p| within(p)).min_by_key(|p| grid_dist(p)))
+            .flatten();
+        match (found, candidate) {
+            (Some(p), _) => Some((p, true)),
+            (None, Some(p)) => Some((p, false)),
+            (None, None) => None,
+        }
+    }
+
     pub fn count_within_distance(&self, point: &(f32, f32), distance: f32) -> usize {
         let range = AABB(*point, distance);
         self.query_aabb(&range).count()
     }
+
+    /// Counts the points inside `rect`. Quadtree cells entirely inside it are
+    /// counted by the store as a whole, which a `CountedMap` does in O(log n),
+    /// only the cells on the border are split down to a handful of points.
+    pub fn count_in_range(&self, rect: Rect) -> usize {
+        let (mut whole, mut border) = (0, 0);
+        self.visit_cells(rect, |start, end| whole += self.tree.count(start ..= end), |_| border += 1);
+        whole + border
+    }
+
+    /// Splits `rect` into the quadtree cells entirely inside it, passed as key ranges to `whole`,
+    /// and the entries inside it from cells on its border, passed to `entry`.
+    pub(crate) fn visit_cells<'a>(
+        &'a self,
+        rect: Rect,
+        mut whole: impl FnMut(u64, u64),
+        mut entry: impl FnMut(&'a Entry<V>),
+    ) {
+        self.walk_cells(rect, |cell| Some(Reverse(cell.key_range().0)), |visit| match visit {
+            Visit::Cell { cell, inside: true, .. } => {
+                let (start, end) = cell.key_range();
+                whole(start, end);
+                Walk::Skip
+            }
+            Visit::Cell { .. } => Walk::Descend,
+            Visit::Entry(e) => {
+                entry(e);
+                Walk::Descend
+            }
+        });
+    }
+
+    /// Walks the quadtree cells overlapping `rect`, largest `priority` first,
+    /// dropping those it gives none. `visit` sees each cell before its points or
+    /// children, and decides whether to descend. Cells with a handful of points
+    /// are not split further, their entries inside `rect` are visited instead.
+    pub(crate) fn walk_cells<'a, P: Ord>(
+        &'a self,
+        rect: Rect,
+        priority: impl Fn(&Cell) -> Option<P>,
+        mut visit: impl FnMut(Visit<'a, '_, V, P>) -> Walk,
+    ) {
+        let Some((min, max)) = self.grid_rect(rect) else { return };
+        let inner = self.grid_inner((min, max));
+        let exact = self.quantizer.is_none();
+        let overlaps = |cell: &Cell| {
+            let (lo, hi) = C::cell_bounds(cell);
+            hi.0 >= min.0 && lo.0 <= max.0 && hi.1 >= min.1 && lo.1 <= max.1
+        };
+        let (z_min, z_max) = C::key_range(&C::new_box(min, max));
+        let root = Cell::from_key(z_min, (z_min ^ z_max).leading_zeros() / 2);
+        let mut cells: BinaryHeap<(P, Cell)> = priority(&root).map(|p| (p, root)).into_iter().collect();
+        while let Some((p, cell)) = cells.pop() {
+            let (lo, hi) = C::cell_bounds(&cell);
+            let inside = inner.0.0 <= lo.0 && hi.0 <= inner.1.0 && inner.0.1 <= lo.1 && hi.1 <= inner.1.1;
+            match visit(Visit::Cell { cell: &cell, priority: &p, inside }) {
+                Walk::Descend => {}
+                Walk::Skip => continue,
+                Walk::Stop => return,
+            }
+            let (start, end) = cell.key_range();
+            let few = self.tree.count_at_most(start ..= end, 16);
+            if few == 0 { continue };
+            if cell.level() == Cell::MAX_LEVEL || few < 16 {
+                for (k, e) in self.tree.range(start ..= end) {
+                    let p = C::decode(*k);
+                    if min.0 <= p.0 && p.0 <= max.0 && min.1 <= p.1 && p.1 <= max.1 && (exact || rect_contains(&rect, &e.0)) {
+                        if let Walk::Stop = visit(Visit::Entry(e)) { return };
+                    }
+                }
+                continue;
+            }
+            for child in cell.children().into_iter().flatten().filter(overlaps) {
+                cells.extend(priority(&child).map(|p| (p, child)));
+            }
+        }
+    }
+}
+
+/// What `QuadTree::walk_cells` has reached.
+pub(crate) enum Visit<'a, 'c, V, P> {
+    /// A cell overlapping the rectangle, `inside` when all its points are in it.
+    Cell { cell: &'c Cell, priority: &'c P, inside: bool },
+    /// An entry inside the rectangle, from a cell that was not split.
+    Entry(&'a Entry<V>),
+}
+
+pub(crate) enum Walk {
+    Descend,
+    /// Leave the cell out, its points and children are not visited.
+    Skip,
+    Stop,
+}
+
+// A `Cell`'s coordinates and bounds are those of its Morton keys.
+impl<V, S: Store<Entry<V>>> QuadTree<V, S> {
+    /// All points and their values inside a quadtree cell.
+    pub fn query_cell(&self, cell: &Cell) -> impl Iterator<Item = (&(f32, f32), &V)> {
+        let (min, max) = cell.key_range();
+        self.tree.range(min ..= max).map(|(_, e)| (&e.0, &e.1))
+    }
 }
 
-impl Default for QuadTree {
+impl<V, S: Store<Entry<V>>, C: SpaceFillingCurve> Default for QuadTree<V, S, C> {
     fn default() -> Self {
-        Self::new()
+        Self::with_store(S::default())
     }
 }
@@ -1,3 +1,8 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::BitSchedule;
+
 pub fn ordered_float(f: f32) -> u32 {
     let x = f.to_bits();
     if x & (1<<31) == 0 {
@@ -13,23 +18,200 @@ pub fn ordered_float_reverse(x: u32) -> f32 {
         f32::from_bits(x ^ (1<<31))
     }
 }
+/// Like `ordered_float_reverse`, but the NaNs ordered below negative infinity
+/// or above positive infinity become that infinity.
+pub(crate) fn ordered_float_clamped(x: u32) -> f32 {
+    if x < ordered_float(f32::NEG_INFINITY) {
+        f32::NEG_INFINITY
+    } else if x > ordered_float(f32::INFINITY) {
+        f32::INFINITY
+    } else {
+        ordered_float_reverse(x)
+    }
+}
+
+pub fn ordered_f64(f: f64) -> u64 {
+    let x = f.to_bits();
+    if x & (1<<63) == 0 {
+        x ^ (1<<63)
+    } else {
+        !x
+    }
+}
+pub fn ordered_f64_reverse(x: u64) -> f64 {
+    if x & (1<<63) == 0 {
+        f64::from_bits(!x)
+    } else {
+        f64::from_bits(x ^ (1<<63))
+    }
+}
+
+/// Flipping the sign bit moves the negatives below the positives.
+pub fn ordered_i16(i: i16) -> u16 {
+    i as u16 ^ (1<<15)
+}
+pub fn ordered_i16_reverse(x: u16) -> i16 {
+    (x ^ (1<<15)) as i16
+}
+pub fn ordered_i32(i: i32) -> u32 {
+    i as u32 ^ (1<<31)
+}
+pub fn ordered_i32_reverse(x: u32) -> i32 {
+    (x ^ (1<<31)) as i32
+}
+pub fn ordered_i64(i: i64) -> u64 {
+    i as u64 ^ (1<<63)
+}
+pub fn ordered_i64_reverse(x: u64) -> i64 {
+    (x ^ (1<<63)) as i64
+}
+
+/// Whether `pdep` and `pext` are available and fast, checked once and cached.
+/// AMD before Zen 3 (family 19h) runs them in microcode, far slower than
+/// the magic numbers, so those CPUs take the portable path.
+#[cfg(target_arch = "x86_64")]
+fn has_fast_bmi2() -> bool {
+    use std::arch::x86_64::__cpuid;
+    use std::sync::OnceLock;
+
+    static FAST: OnceLock<bool> = OnceLock::new();
+    *FAST.get_or_init(|| {
+        if !is_x86_feature_detected!("bmi2") {
+            return false;
+        }
+        // "AuthenticAMD", or "HygonGenuine" for the Zen 1 based Hygon parts
+        let vendor = __cpuid(0);
+        let amd = matches!(
+            [vendor.ebx, vendor.edx, vendor.ecx],
+            [0x6874_7541, 0x6974_6e65, 0x444d_4163] | [0x6f67_7948, 0x6e65_476e, 0x656e_6975]
+        );
+        let signature = __cpuid(1).eax;
+        let family = match (signature >> 8) & 0xF {
+            0xF => 0xF + ((signature >> 20) & 0xFF),
+            base => base,
+        };
+        !amd || family >= 0x19
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+mod bmi2 {
+    use std::arch::x86_64::{_pdep_u64, _pext_u64};
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn spread_bits_2(input: u32) -> u64 {
+        _pdep_u64(input as u64, 0xAAAA_AAAA_AAAA_AAAA)
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn collapse_bits_2(input: u64) -> u32 {
+        _pext_u64(input, 0x5555_5555_5555_5555) as u32
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn spread_bits_4(input: u16) -> u64 {
+        _pdep_u64(input as u64, 0x8888_8888_8888_8888)
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn collapse_bits_4(input: u64) -> u16 {
+        _pext_u64(input, 0x1111_1111_1111_1111) as u16
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn deposit_bits(input: u64, mask: u64) -> u64 {
+        _pdep_u64(input, mask)
+    }
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn extract_bits(input: u64, mask: u64) -> u64 {
+        _pext_u64(input, mask)
+    }
+}
 
-fn spread_bits_2(mut input: u32) -> u64 {
+/// Spreads the low bits of `input` over the set bits of `mask`, like `pdep`.
+pub(crate) fn deposit_bits(input: u64, mask: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::deposit_bits(input, mask) };
+    }
+    deposit_bits_portable(input, mask)
+}
+
+pub(crate) fn deposit_bits_portable(mut input: u64, mut mask: u64) -> u64 {
     let mut output = 0;
-    for _ in 0..32 {
-        output = (output << 2) | (input & 1) as u64;
+    while mask != 0 {
+        let bit = mask & mask.wrapping_neg();
+        if input & 1 == 1 {
+            output |= bit;
+        }
         input >>= 1;
+        mask ^= bit;
     }
-    output.reverse_bits()
+    output
 }
 
-fn collapse_bits_2(mut input: u64) -> u32 {
+/// Gathers the bits of `input` under the set bits of `mask` into the low bits, like `pext`.
+pub(crate) fn extract_bits(input: u64, mask: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::extract_bits(input, mask) };
+    }
+    extract_bits_portable(input, mask)
+}
+
+pub(crate) fn extract_bits_portable(input: u64, mut mask: u64) -> u64 {
     let mut output = 0;
-    for _ in 0..32 {
-        output = (output << 1) | (input & 1) as u32;
-        input >>= 2;
+    let mut i = 0;
+    while mask != 0 {
+        let bit = mask & mask.wrapping_neg();
+        if input & bit != 0 {
+            output |= 1 << i;
+        }
+        i += 1;
+        mask ^= bit;
     }
-    output.reverse_bits()
+    output
+}
+
+/// Bit `i` of the input goes to bit `2i + 1`.
+#[inline]
+pub(crate) fn spread_bits_2_magic(input: u32) -> u64 {
+    let mut x = input as u64;
+    x = (x | x << 16) & 0x0000_FFFF_0000_FFFF;
+    x = (x | x << 8) & 0x00FF_00FF_00FF_00FF;
+    x = (x | x << 4) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | x << 2) & 0x3333_3333_3333_3333;
+    x = (x | x << 1) & 0x5555_5555_5555_5555;
+    x << 1
+}
+
+/// Bit `2i` of the input goes to bit `i`.
+#[inline]
+pub(crate) fn collapse_bits_2_magic(input: u64) -> u32 {
+    let mut x = input & 0x5555_5555_5555_5555;
+    x = (x | x >> 1) & 0x3333_3333_3333_3333;
+    x = (x | x >> 2) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | x >> 4) & 0x00FF_00FF_00FF_00FF;
+    x = (x | x >> 8) & 0x0000_FFFF_0000_FFFF;
+    x = (x | x >> 16) & 0x0000_0000_FFFF_FFFF;
+    x as u32
+}
+
+fn spread_bits_2(input: u32) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::spread_bits_2(input) };
+    }
+    spread_bits_2_magic(input)
+}
+
+fn collapse_bits_2(input: u64) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::collapse_bits_2(input) };
+    }
+    collapse_bits_2_magic(input)
 }
 
 pub fn morton_2(x: u32, y: u32) -> u64 {
@@ -41,22 +223,42 @@ pub fn morton_reverse_2(z: u64) -> (u32, u32) {
 }
 
 
-fn spread_bits_4(mut input: u16) -> u64 {
-    let mut output = 0;
-    for _ in 0..16 {
-        output = (output << 4) | (input & 1) as u64;
-        input >>= 1;
+/// Bit `i` of the input goes to bit `4i + 3`.
+#[inline]
+pub(crate) fn spread_bits_4_magic(input: u16) -> u64 {
+    let mut x = input as u64;
+    x = (x | x << 24) & 0x0000_00FF_0000_00FF;
+    x = (x | x << 12) & 0x000F_000F_000F_000F;
+    x = (x | x << 6) & 0x0303_0303_0303_0303;
+    x = (x | x << 3) & 0x1111_1111_1111_1111;
+    x << 3
+}
+
+/// Bit `4i` of the input goes to bit `i`.
+#[inline]
+pub(crate) fn collapse_bits_4_magic(input: u64) -> u16 {
+    let mut x = input & 0x1111_1111_1111_1111;
+    x = (x | x >> 3) & 0x0303_0303_0303_0303;
+    x = (x | x >> 6) & 0x000F_000F_000F_000F;
+    x = (x | x >> 12) & 0x0000_00FF_0000_00FF;
+    x = (x | x >> 24) & 0x0000_0000_0000_FFFF;
+    x as u16
+}
+
+fn spread_bits_4(input: u16) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::spread_bits_4(input) };
     }
-    output.reverse_bits()
+    spread_bits_4_magic(input)
 }
 
-fn collapse_bits_4(mut input: u64) -> u16 {
-    let mut output = 0;
-    for _ in 0..16 {
-        output = (output << 1) | (input & 1) as u16;
-        input >>= 4;
+fn collapse_bits_4(input: u64) -> u16 {
+    #[cfg(target_arch = "x86_64")]
+    if has_fast_bmi2() {
+        return unsafe { bmi2::collapse_bits_4(input) };
     }
-    output.reverse_bits()
+    collapse_bits_4_magic(input)
 }
 
 pub fn morton_4(x: u16, y: u16, z: u16, w: u16) -> u64 {
@@ -66,6 +268,16 @@ pub fn morton_4(x: u16, y: u16, z: u16, w: u16) -> u64 {
     spread_bits_4(w) >> 3
 }
 
+/// `morton_4` over signed coordinates, mapped through `ordered_i16` so negative ones sort first.
+pub fn morton_4_signed(x: i16, y: i16, z: i16, w: i16) -> u64 {
+    morton_4(ordered_i16(x), ordered_i16(y), ordered_i16(z), ordered_i16(w))
+}
+
+pub fn morton_reverse_4_signed(z: u64) -> (i16, i16, i16, i16) {
+    let (x, y, z, w) = morton_reverse_4(z);
+    (ordered_i16_reverse(x), ordered_i16_reverse(y), ordered_i16_reverse(z), ordered_i16_reverse(w))
+}
+
 pub fn morton_reverse_4(z: u64) -> (u16, u16, u16, u16) {
     (
         collapse_bits_4(z >> 3),
@@ -75,69 +287,214 @@ pub fn morton_reverse_4(z: u64) -> (u16, u16, u16, u16) {
     )
 }
 
-const fn dim_masks<const D: usize>() -> [u64; D] {
-    let mut masks = [0; D];
-    masks[D-1] = 1;
-    let mut shf = D;
-    while shf < 64 {
-        masks[D-1] |= masks[D-1] << shf;
-        shf *= 2;
-    }
-    let mut i = D-1;
-    while i > 0 {
-        masks[i-1] = masks[i] << 1;
-        i -= 1;
-    }
-    masks
+// Dilated integer arithmetic: filling the other axes' bits with ones lets carries
+// ripple straight through them, and clearing them lets borrows do the same.
+fn dilated_add(z: u64, mask: u64, delta: u64) -> u64 {
+    (z | !mask).wrapping_add(delta & mask) & mask | z & !mask
 }
 
-pub struct ZOrderIndexer<const D: usize>((u64, u64));
+fn dilated_sub(z: u64, mask: u64, delta: u64) -> u64 {
+    (z & mask).wrapping_sub(delta & mask) & mask | z & !mask
+}
+
+/// The key `dx` further along x, wrapping like `u32::wrapping_add`.
+pub fn morton_add_x(z: u64, dx: u32) -> u64 {
+    dilated_add(z, 0xAAAA_AAAA_AAAA_AAAA, spread_bits_2(dx))
+}
+pub fn morton_sub_x(z: u64, dx: u32) -> u64 {
+    dilated_sub(z, 0xAAAA_AAAA_AAAA_AAAA, spread_bits_2(dx))
+}
+pub fn morton_add_y(z: u64, dy: u32) -> u64 {
+    dilated_add(z, 0x5555_5555_5555_5555, spread_bits_2(dy) >> 1)
+}
+pub fn morton_sub_y(z: u64, dy: u32) -> u64 {
+    dilated_sub(z, 0x5555_5555_5555_5555, spread_bits_2(dy) >> 1)
+}
+
+/// The key `d` further along `axis`, counted in the argument order of `morton_4`,
+/// wrapping like `u16::wrapping_add`.
+pub fn morton_4_add(z: u64, axis: usize, d: u16) -> u64 {
+    assert!(axis < 4);
+    dilated_add(z, 0x8888_8888_8888_8888 >> axis, spread_bits_4(d) >> axis)
+}
+pub fn morton_4_sub(z: u64, axis: usize, d: u16) -> u64 {
+    assert!(axis < 4);
+    dilated_sub(z, 0x8888_8888_8888_8888 >> axis, spread_bits_4(d) >> axis)
+}
+
+pub struct ZOrderIndexer<const D: usize> {
+    bounds: (u64, u64),
+    masks: [u64; D],
+}
 
 type Point16 = (u16, u16);
 type Rect16 = (Point16, Point16);
+type SignedRect16 = ((i16, i16), (i16, i16));
+
+/// A box whose minimum lies past its maximum on `axis`, counted in the
+/// argument order of `morton_2`, `morton_4` or the schedule's dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InvertedBounds {
+    pub axis: usize,
+}
+
+impl fmt::Display for InvertedBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "min is greater than max on axis {}", self.axis)
+    }
+}
+
+impl Error for InvertedBounds {}
 
 impl ZOrderIndexer<2> {
     pub fn new(min: (u32, u32), max: (u32, u32)) -> Self {
-        let min = morton_2(min.0, min.1);
-        let max = morton_2(max.0, max.1);
-        Self::from_morton(min, max)
+        Self::try_new(min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+    pub fn try_new(min: (u32, u32), max: (u32, u32)) -> Result<Self, InvertedBounds> {
+        Self::try_from_morton(morton_2(min.0, min.1), morton_2(max.0, max.1))
+    }
+    /// Like `new`, swapping `min` and `max` on any axis where they are inverted.
+    pub fn new_normalized(min: (u32, u32), max: (u32, u32)) -> Self {
+        Self::from_morton_normalized(morton_2(min.0, min.1), morton_2(max.0, max.1))
     }
 }
 
 impl ZOrderIndexer<4> {
     pub fn new(min: Rect16, max: Rect16) -> Self {
+        Self::try_new(min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+    pub fn try_new(min: Rect16, max: Rect16) -> Result<Self, InvertedBounds> {
+        let min = morton_4(min.0.0, min.0.1, min.1.0, min.1.1);
+        let max = morton_4(max.0.0, max.0.1, max.1.0, max.1.1);
+        Self::try_from_morton(min, max)
+    }
+    pub fn new_normalized(min: Rect16, max: Rect16) -> Self {
         let min = morton_4(min.0.0, min.0.1, min.1.0, min.1.1);
         let max = morton_4(max.0.0, max.0.1, max.1.0, max.1.1);
-        Self::from_morton(min, max)
+        Self::from_morton_normalized(min, max)
+    }
+    /// The box between signed corners, keyed like `morton_4_signed`.
+    pub fn new_signed(min: SignedRect16, max: SignedRect16) -> Self {
+        Self::try_new_signed(min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+    pub fn try_new_signed(min: SignedRect16, max: SignedRect16) -> Result<Self, InvertedBounds> {
+        let min = morton_4_signed(min.0.0, min.0.1, min.1.0, min.1.1);
+        let max = morton_4_signed(max.0.0, max.0.1, max.1.0, max.1.1);
+        Self::try_from_morton(min, max)
     }
 }
 impl<const D: usize> ZOrderIndexer<D> {
-    const DIMS: [u64; D] = dim_masks::<D>();
     pub fn from_morton(min: u64, max: u64) -> Self {
-        assert!(Self::DIMS.iter().all(|dim| min & dim <= max & dim));
-        Self((min, max))
+        Self::with_schedule(&BitSchedule::interleaved(), min, max)
+    }
+    pub fn try_from_morton(min: u64, max: u64) -> Result<Self, InvertedBounds> {
+        Self::try_with_schedule(&BitSchedule::interleaved(), min, max)
+    }
+    pub fn from_morton_normalized(min: u64, max: u64) -> Self {
+        Self::with_schedule_normalized(&BitSchedule::interleaved(), min, max)
+    }
+    /// The box between the keys `min` and `max` of `schedule`.
+    pub fn with_schedule(schedule: &BitSchedule<D>, min: u64, max: u64) -> Self {
+        Self::try_with_schedule(schedule, min, max).unwrap_or_else(|e| panic!("{e}"))
+    }
+    pub fn try_with_schedule(schedule: &BitSchedule<D>, min: u64, max: u64) -> Result<Self, InvertedBounds> {
+        let masks = *schedule.masks();
+        match masks.iter().position(|dim| min & dim > max & dim) {
+            Some(axis) => Err(InvertedBounds { axis }),
+            None => Ok(Self { bounds: (min, max), masks }),
+        }
+    }
+    /// The box between `min` and `max`, taking the smaller of the two on each axis as its minimum.
+    pub fn with_schedule_normalized(schedule: &BitSchedule<D>, min: u64, max: u64) -> Self {
+        let masks = *schedule.masks();
+        let (lo, hi) = masks.iter().fold((0, 0), |(lo, hi), dim| {
+            let (a, b) = (min & dim, max & dim);
+            (lo | a.min(b), hi | a.max(b))
+        });
+        Self { bounds: (lo, hi), masks }
     }
     pub fn bounds(&self) -> &(u64, u64) {
-        &self.0
+        &self.bounds
     }
     pub fn contains(&self, z: u64) -> bool {
-        Self::DIMS.iter().all(|dim|
-            z & dim >= self.0.0 & dim &&
-            z & dim <= self.0.1 & dim)
+        self.masks.iter().all(|dim|
+            z & dim >= self.bounds.0 & dim &&
+            z & dim <= self.bounds.1 & dim)
+    }
+    /// Splits the box into at most `max_ranges` sorted, disjoint runs of keys,
+    /// for stores that can only scan key ranges.
+    ///
+    /// Blocks of keys are halved one bit at a time. If the box is described
+    /// exactly before the limit is hit the runs are exact, otherwise the finest
+    /// split is cut down by filling its smallest gaps, so the runs never cover
+    /// more than those of the last split that fit.
+    pub fn key_ranges(&self, max_ranges: usize) -> Vec<(u64, u64)> {
+        let max_ranges = max_ranges.max(1);
+        let (min, max) = self.bounds;
+        let bits = 64 - (min ^ max).leading_zeros();
+        let start = min & !low_bits(bits);
+        let mut blocks = vec![(start, bits, self.overlap(start, bits) == Some(true))];
+        loop {
+            if blocks.iter().all(|block| block.2) {
+                return clamp(merge_blocks(&blocks), self.bounds);
+            }
+            let mut split = Vec::with_capacity(blocks.len() * 2);
+            for &(lo, bits, full) in &blocks {
+                if full {
+                    split.push((lo, bits, full));
+                    continue;
+                }
+                for lo in [lo, lo | 1 << (bits - 1)] {
+                    if let Some(full) = self.overlap(lo, bits - 1) {
+                        split.push((lo, bits - 1, full));
+                    }
+                }
+            }
+            blocks = split;
+            let ranges = merge_blocks(&blocks);
+            if ranges.len() > max_ranges {
+                return clamp(fill_gaps(ranges, max_ranges), self.bounds);
+            }
+        }
+    }
+    /// Whether the keys from `lo` through its low `bits` are all inside the box,
+    /// or `None` if none are.
+    fn overlap(&self, lo: u64, bits: u32) -> Option<bool> {
+        let hi = lo | low_bits(bits);
+        let mut full = true;
+        for dim in &self.masks {
+            let (first, last) = (lo & dim, hi & dim);
+            let (min, max) = (self.bounds.0 & dim, self.bounds.1 & dim);
+            if last < min || first > max {
+                return None;
+            }
+            full &= min <= first && last <= max;
+        }
+        Some(full)
     }
     pub fn next_zorder_index(&self, z: u64) -> Option<u64> {
-        if self.contains(z + 1) {
-            return Some(z + 1);
+        // Carry through the bits no dimension uses, so the next key skips over them
+        let used = self.masks.iter().fold(0, |used, dim| used | dim);
+        let next = (z | !used).checked_add(1)? & used;
+        if self.contains(next) {
+            return Some(next);
         }
         let mut bigmin = None;
-        let (mut min_v, mut max_v) = self.0;
-        // One in all dimensions but the current one, and in all past bits
-        // Preserves the value of those bits, zeros the current dimension
-        let mut load_mask = !Self::DIMS[0];
-        // One in the current dimension, except past bits
-        let mut load_ones = Self::DIMS[0] >> D;
-        // Each bit draws an axis in some dimension, that we use to narrow down our search space
-        for bit in (0..64).rev() {
+        let (mut min_v, mut max_v) = self.bounds;
+        // Each bit draws an axis in some dimension, that we use to narrow down our search space.
+        // Where the target and both bounds agree nothing changes, so only the others are visited.
+        let mut below = u64::MAX;
+        loop {
+            let differ = ((z ^ min_v) | (z ^ max_v)) & below;
+            if differ == 0 { break };
+            let bit = 63 - differ.leading_zeros();
+            below = (1 << bit) - 1;
+            let Some(dim) = self.masks.iter().find(|dim| *dim >> bit & 1 == 1) else { continue };
+            // One in all dimensions but the current one, and in all past bits
+            // Preserves the value of those bits, zeros the current dimension
+            let load_mask = !(dim & (u64::MAX >> (63 - bit)));
+            // One in the current dimension, except past bits
+            let load_ones = dim & ((1 << bit) - 1);
             let z_bit = z >> bit & 1;
             let i_bit = min_v >> bit & 1;
             let a_bit = max_v >> bit & 1;
@@ -167,10 +524,52 @@ impl<const D: usize> ZOrderIndexer<D> {
                 (1, 1, 1) => (),
                 _ => unreachable!()
             }
-            load_ones >>= 1;
-            load_mask >>= 1;
-            load_mask |= 1<<63;
         }
         bigmin
     }
 }
+
+fn low_bits(bits: u32) -> u64 {
+    u64::MAX.checked_shr(64 - bits).unwrap_or(0)
+}
+
+fn merge_blocks(blocks: &[(u64, u32, bool)]) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for &(lo, bits, _) in blocks {
+        let hi = lo | low_bits(bits);
+        match ranges.last_mut() {
+            Some(last) if last.1.checked_add(1) == Some(lo) => last.1 = hi,
+            _ => ranges.push((lo, hi)),
+        }
+    }
+    ranges
+}
+
+/// Trims the outer ranges to the first and last keys of the box.
+fn clamp(mut ranges: Vec<(u64, u64)>, (min, max): (u64, u64)) -> Vec<(u64, u64)> {
+    if let Some(first) = ranges.first_mut() {
+        first.0 = first.0.max(min);
+    }
+    if let Some(last) = ranges.last_mut() {
+        last.1 = last.1.min(max);
+    }
+    ranges
+}
+
+/// Joins neighbouring ranges across the smallest gaps until `max_ranges` are left.
+fn fill_gaps(ranges: Vec<(u64, u64)>, max_ranges: usize) -> Vec<(u64, u64)> {
+    let mut gaps: Vec<usize> = (1..ranges.len()).collect();
+    gaps.sort_by_key(|&i| ranges[i].0 - ranges[i - 1].1);
+    let mut fill = vec![false; ranges.len()];
+    for &i in &gaps[..ranges.len() - max_ranges] {
+        fill[i] = true;
+    }
+    let mut joined: Vec<(u64, u64)> = Vec::with_capacity(max_ranges);
+    for (range, fill) in ranges.into_iter().zip(fill) {
+        match joined.last_mut() {
+            Some(last) if fill => last.1 = range.1,
+            _ => joined.push(range),
+        }
+    }
+    joined
+}
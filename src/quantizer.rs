@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Rect;
+
+/// Low bits of each grid coordinate a quantized tree keeps to tell apart the points
+/// sharing a step, so a step holds up to `2^(2 * SLOT_BITS)` of them.
+pub(crate) const SLOT_BITS: u32 = 8;
+
+/// The last step on each axis, the rest of the u32 grid goes to the slots.
+const MAX_STEP: u32 = u32::MAX >> SLOT_BITS;
+
+/// Maps world coordinates linearly onto a grid of `2^24` steps per axis,
+/// `resolution` world units per grid step, starting at the min corner of the
+/// world bounds. Unlike `ordered_float`, which spends most of the grid on tiny
+/// magnitudes, every step covers the same distance, so nearby points get nearby
+/// keys. Coordinates outside the bounds are clamped to its edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantizer {
+    origin: (f64, f64),
+    resolution: f64,
+}
+
+impl Quantizer {
+    pub fn new(bounds: Rect, resolution: f32) -> Self {
+        let resolution = resolution as f64;
+        assert!(resolution > 0.0, "resolution must be positive");
+        let ((x0, y0), (x1, y1)) = bounds;
+        let steps = f64::max(x1 as f64 - x0 as f64, y1 as f64 - y0 as f64) / resolution;
+        assert!(steps <= MAX_STEP as f64, "bounds need more than 2^24 steps at this resolution");
+        Quantizer { origin: (x0 as f64, y0 as f64), resolution }
+    }
+
+    /// The finest grid covering `bounds`.
+    pub fn fit(bounds: Rect) -> Self {
+        let ((x0, y0), (x1, y1)) = bounds;
+        let extent = f64::max(x1 as f64 - x0 as f64, y1 as f64 - y0 as f64);
+        Quantizer {
+            origin: (x0 as f64, y0 as f64),
+            resolution: f64::max(extent / MAX_STEP as f64, f64::MIN_POSITIVE),
+        }
+    }
+
+    pub fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    pub fn quantize(&self, point: (f32, f32)) -> (u32, u32) {
+        // Float to int casts saturate, which does the clamping below the origin
+        (
+            (((point.0 as f64 - self.origin.0) / self.resolution) as u32).min(MAX_STEP),
+            (((point.1 as f64 - self.origin.1) / self.resolution) as u32).min(MAX_STEP),
+        )
+    }
+
+    /// The world rectangle covered by the grid cells from `min` to `max`.
+    pub fn world_rect(&self, min: (u32, u32), max: (u32, u32)) -> Rect {
+        let world = |cell: f64, origin: f64| (origin + cell * self.resolution) as f32;
+        (
+            (world(min.0 as f64, self.origin.0), world(min.1 as f64, self.origin.1)),
+            (world(max.0 as f64 + 1.0, self.origin.0), world(max.1 as f64 + 1.0, self.origin.1)),
+        )
+    }
+}
+
+/// A point a quantized tree refused, because every slot of its grid step is taken,
+/// one of them by `existing`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellCollision<V> {
+    pub point: (f32, f32),
+    pub value: V,
+    pub existing: (f32, f32),
+}
+
+impl<V> fmt::Display for CellCollision<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} falls in the full grid step of {:?}", self.point, self.existing)
+    }
+}
+
+impl<V: fmt::Debug> Error for CellCollision<V> {}
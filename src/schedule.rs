@@ -0,0 +1,91 @@
+use crate::morton::{deposit_bits, extract_bits};
+use crate::ZOrderIndexer;
+
+/// Which dimension each bit of a key belongs to. Morton keys give every one of
+/// the `D` dimensions `64 / D` bits, taken in turn, with the first dimension
+/// on the most significant bit of each round. A schedule can give dimensions
+/// different numbers of bits, in any order, and leave the top bits unused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BitSchedule<const D: usize> {
+    masks: [u64; D],
+}
+
+impl<const D: usize> BitSchedule<D> {
+    /// The schedule of `morton_2` and `morton_4`.
+    pub const fn interleaved() -> Self {
+        let mut masks = [0; D];
+        let mut bit = 0;
+        while bit < 64 {
+            masks[D - 1 - bit % D] |= 1 << bit;
+            bit += 1;
+        }
+        BitSchedule { masks }
+    }
+
+    /// Dimension `d` owns the bits set in `masks[d]`.
+    pub fn from_masks(masks: [u64; D]) -> Self {
+        let mut used = 0;
+        for mask in masks {
+            assert!(used & mask == 0, "dimensions share bits");
+            used |= mask;
+        }
+        BitSchedule { masks }
+    }
+
+    /// `pattern[i]` is the dimension of the `i`th bit, from the most significant
+    /// one. The pattern fills the low `pattern.len()` bits of the key.
+    pub fn from_pattern(pattern: &[usize]) -> Self {
+        assert!(pattern.len() <= 64, "more than 64 bits");
+        let mut masks = [0; D];
+        for (i, &dim) in pattern.iter().rev().enumerate() {
+            masks[dim] |= 1 << i;
+        }
+        BitSchedule { masks }
+    }
+
+    /// Takes dimensions in turn from the most significant bit, skipping those
+    /// that ran out of bits, so the dimensions with the most bits have their
+    /// finest ones alone at the bottom. `[40, 12, 12]` interleaves the top 12
+    /// bits of the first dimension with the others and ends with 28 of its own.
+    pub fn round_robin(bits: [u32; D]) -> Self {
+        let mut left = bits;
+        let mut pattern = Vec::new();
+        while left.iter().any(|&b| b > 0) {
+            for (dim, b) in left.iter_mut().enumerate() {
+                if *b > 0 {
+                    *b -= 1;
+                    pattern.push(dim);
+                }
+            }
+        }
+        Self::from_pattern(&pattern)
+    }
+
+    pub fn masks(&self) -> &[u64; D] {
+        &self.masks
+    }
+
+    /// Number of bits of each dimension.
+    pub fn bits(&self) -> [u32; D] {
+        self.masks.map(u64::count_ones)
+    }
+
+    /// The key of a point, whose coordinates must fit in their number of bits.
+    pub fn encode(&self, point: [u64; D]) -> u64 {
+        let mut key = 0;
+        for (coordinate, mask) in point.into_iter().zip(self.masks) {
+            debug_assert!(coordinate >> mask.count_ones() == 0 || mask.count_ones() == 64);
+            key |= deposit_bits(coordinate, mask);
+        }
+        key
+    }
+
+    pub fn decode(&self, key: u64) -> [u64; D] {
+        self.masks.map(|mask| extract_bits(key, mask))
+    }
+
+    /// The box between the points `min` and `max`.
+    pub fn indexer(&self, min: [u64; D], max: [u64; D]) -> ZOrderIndexer<D> {
+        ZOrderIndexer::with_schedule(self, self.encode(min), self.encode(max))
+    }
+}
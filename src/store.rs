@@ -0,0 +1,88 @@
+use std::collections::{btree_map, BTreeMap};
+use std::ops::{Bound, RangeBounds};
+
+/// The sorted container a `QuadTree` keeps its Morton keys in.
+pub trait Store<T>: Default {
+    type Range<'a>: DoubleEndedIterator<Item = (&'a u64, &'a T)> + Clone
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn insert(&mut self, key: u64, value: T) -> Option<T>;
+    fn range<R: RangeBounds<u64>>(&self, range: R) -> Self::Range<'_>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of keys in `range`. Stores that know their subtree sizes
+    /// answer without visiting the keys.
+    fn count<R: RangeBounds<u64>>(&self, range: R) -> usize {
+        self.range(range).count()
+    }
+
+    /// Like `count`, but may stop looking once `limit` keys are found.
+    fn count_at_most<R: RangeBounds<u64>>(&self, range: R, limit: usize) -> usize {
+        self.range(range).take(limit).count()
+    }
+
+    /// Number of keys strictly smaller than `key`.
+    fn rank(&self, key: u64) -> usize {
+        self.count(..key)
+    }
+
+    /// The entry with `i` keys before it.
+    fn select(&self, i: usize) -> Option<(&u64, &T)> {
+        self.range(..).nth(i)
+    }
+
+    /// The keys `select` finds for each of `ranks`, which are in ascending order.
+    /// Without order statistics they are all read in one pass over the keys.
+    fn select_keys(&self, ranks: &[usize]) -> Vec<Option<u64>> {
+        let mut keys = self.range(..).map(|(k, _)| *k).enumerate();
+        let mut last = None;
+        ranks.iter()
+            .map(|&i| {
+                if last.is_none_or(|(j, _)| j != i) {
+                    last = keys.find(|(j, _)| *j == i);
+                }
+                last.map(|(_, k)| k)
+            })
+            .collect()
+    }
+
+    /// An upper bound on the values in `range`, for stores that keep per-subtree maximums.
+    fn upper_bound<R: RangeBounds<u64>>(&self, _range: R) -> Option<f64> {
+        None
+    }
+}
+
+impl<T> Store<T> for BTreeMap<u64, T> {
+    type Range<'a> = btree_map::Range<'a, u64, T> where T: 'a;
+
+    fn insert(&mut self, key: u64, value: T) -> Option<T> {
+        BTreeMap::insert(self, key, value)
+    }
+    fn range<R: RangeBounds<u64>>(&self, range: R) -> Self::Range<'_> {
+        BTreeMap::range(self, range)
+    }
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+}
+
+/// Turns any range of keys into an inclusive one, `None` when it is empty.
+pub(crate) fn inclusive<R: RangeBounds<u64>>(range: &R) -> Option<(u64, u64)> {
+    let start = match range.start_bound() {
+        Bound::Included(&k) => k,
+        Bound::Excluded(&k) => k.checked_add(1)?,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&k) => k,
+        Bound::Excluded(&k) => k.checked_sub(1)?,
+        Bound::Unbounded => u64::MAX,
+    };
+    (start <= end).then_some((start, end))
+}
@@ -1,5 +1,11 @@
 use super::*;
 
+/// A rectangle over the points of `generate_random_points(_, 1e2)`, at times sticking out of them.
+fn random_rect(rng: &mut impl Rng) -> Rect {
+    let min = (rng.gen_range(-1e1..1e2), rng.gen_range(-1e1..1e2));
+    (min, (min.0 + rng.gen_range(0.0..6e1), min.1 + rng.gen_range(0.0..6e1)))
+}
+
 #[test]
 fn test_indexer() {
     let zi = ZOrderIndexer::<2>::from_morton(0, 6);
@@ -30,6 +36,30 @@ fn test_morton() {
         }
     }
 }
+
+#[test]
+fn morton_matches_bit_loops() {
+    use crate::morton::{collapse_bits_2_magic, collapse_bits_4_magic, spread_bits_2_magic, spread_bits_4_magic};
+    let spread = |input: u64, bits: usize, dims: usize| {
+        (0..bits).fold(0, |z, i| z | (input >> i & 1) << (dims * i + dims - 1))
+    };
+    let collapse = |z: u64, bits: usize, dims: usize| {
+        (0..bits).fold(0, |x, i| x | (z >> (dims * i) & 1) << i)
+    };
+    let mut rng = rand::thread_rng();
+    for _ in 0..10000 {
+        let (x, y, z): (u32, u32, u64) = (rng.gen(), rng.gen(), rng.gen());
+        assert_eq!(spread(x as u64, 32, 2), spread_bits_2_magic(x));
+        assert_eq!(collapse(z, 32, 2), collapse_bits_2_magic(z) as u64);
+        assert_eq!(spread(x as u64 & 0xFFFF, 16, 4), spread_bits_4_magic(x as u16));
+        assert_eq!(collapse(z, 16, 4), collapse_bits_4_magic(z) as u64);
+        assert_eq!(spread(x as u64, 32, 2) | spread(y as u64, 32, 2) >> 1, morton_2(x, y));
+        assert_eq!((collapse(z >> 1, 32, 2) as u32, collapse(z, 32, 2) as u32), morton_reverse_2(z));
+        let (a, b, c, d) = (x as u16, (x >> 16) as u16, y as u16, (y >> 16) as u16);
+        assert_eq!((a, b, c, d), morton_reverse_4(morton_4(a, b, c, d)));
+    }
+}
+
 #[test]
 fn test_count_neighbors_correctness() {
     let mut rng = rand::thread_rng();
@@ -99,3 +129,955 @@ fn nns() {
         assert_eq!(a, b);
     }
 }
+
+#[test]
+fn paginated_query() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10 {
+        let mut quad = QuadTree::new();
+        for p in generate_random_points(2000, 1e2) {
+            quad.insert(p);
+        }
+        let min = (ordered_float(20.0), ordered_float(30.0));
+        let max = (ordered_float(70.0), ordered_float(60.0));
+        let expected: Vec<_> = quad.query(min, max).copied().collect();
+
+        let mut pages = Vec::new();
+        let mut cursor = quad.query_cursor(min, max);
+        pages.extend(cursor.by_ref().take(50).map(|(p, _)| *p));
+        let mut token = cursor.token();
+        while let Some(t) = token {
+            let t: QueryToken = t.to_string().parse().unwrap();
+            let mut cursor = quad.query_from(t, min, max);
+            let page: Vec<_> = cursor.by_ref().take(50).map(|(p, _)| *p).collect();
+            if page.is_empty() { break };
+            pages.extend(page);
+            token = cursor.token();
+        }
+        assert_eq!(expected, pages);
+
+        // Points inserted behind the cursor are not revisited, points ahead are picked up
+        let mut cursor = quad.query_cursor(min, max);
+        let first: Vec<_> = cursor.by_ref().take(expected.len() / 2).map(|(p, _)| *p).collect();
+        let t = cursor.token().unwrap();
+        let inserted: Vec<_> = (0..100)
+            .map(|_| (rng.gen_range(20.0..70.0), rng.gen_range(30.0..60.0)))
+            .collect();
+        for p in &inserted {
+            quad.insert(*p);
+        }
+        let rest: Vec<_> = quad.query_from(t, min, max).map(|(p, _)| *p).collect();
+        let ahead = inserted.iter()
+            .filter(|p| morton_2(ordered_float(p.0), ordered_float(p.1)) > t.0)
+            .count();
+        assert!(rest.iter().all(|p| !first.contains(p)));
+        assert_eq!(rest.len(), expected.len() - first.len() + ahead);
+    }
+}
+
+#[test]
+fn bounded_nns() {
+    let mut rng = rand::thread_rng();
+    let dist = |a: &(f32, f32), b: &(f32, f32)| f32::max((a.0 - b.0).abs(), (a.1 - b.1).abs());
+    let grid_dist = |a: &(f32, f32), b: &(f32, f32)| {
+        u32::max(ordered_float(a.0).abs_diff(ordered_float(b.0)), ordered_float(a.1).abs_diff(ordered_float(b.1)))
+    };
+    for _ in 0..100 {
+        let num_points = rng.gen_range(1..1000);
+        let points = generate_random_points(num_points, 1e2);
+        let mut quad = QuadTree::new();
+        for p in &points {
+            quad.insert(*p);
+        }
+        let origin = (rng.gen_range(0.0..1e2), rng.gen_range(0.0..1e2));
+        let best = points.iter().map(|p| grid_dist(p, &origin)).min().unwrap();
+        assert_eq!(grid_dist(quad.nearest(origin).next().unwrap(), &origin), best);
+
+        let epsilon = rng.gen_range(0.0..1.0);
+        let (p, exact) = quad.nearest_approx(origin, epsilon).unwrap();
+        assert!(grid_dist(p, &origin) as f64 <= best as f64 * (1.0 + epsilon as f64));
+        if exact { assert_eq!(grid_dist(p, &origin), best) };
+        let (p, exact) = quad.nearest_approx(origin, 0.0).unwrap();
+        assert!(exact);
+        assert_eq!(grid_dist(p, &origin), best);
+
+        let max_distance = rng.gen_range(0.0..1e1);
+        let best = points.iter().filter(|p| dist(p, &origin) <= max_distance).map(|p| grid_dist(p, &origin)).min();
+        match quad.nearest_within(origin, max_distance) {
+            Some((p, exact)) => {
+                assert!(exact);
+                assert!(dist(p, &origin) <= max_distance);
+                assert_eq!(Some(grid_dist(p, &origin)), best);
+            }
+            None => assert_eq!(best, None),
+        }
+    }
+    assert_eq!(QuadTree::<()>::new().nearest_within((0.0, 0.0), 5.0), None);
+    assert_eq!(QuadTree::<()>::new().nearest_approx((0.0, 0.0), 0.5), None);
+}
+
+#[test]
+fn filtered_nns() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+        let num_points = rng.gen_range(100..1000);
+        let mut quad = QuadTree::new();
+        let mut points = Vec::new();
+        for p in generate_random_points(num_points, 1e2) {
+            let label = rng.gen_range(0..4u32);
+            if quad.insert_value(p, label).is_none() {
+                points.push((p, label));
+            }
+        }
+        let origin = (rng.gen_range(0.0..1e2), rng.gen_range(0.0..1e2));
+        let dist = |p: &(f32, f32)| u32::max(
+            u32::abs_diff(ordered_float(p.0), ordered_float(origin.0)),
+            u32::abs_diff(ordered_float(p.1), ordered_float(origin.1)),
+        );
+        let mut expected: Vec<_> = points.iter()
+            .filter(|(_, label)| *label == 0)
+            .map(|(p, _)| dist(p))
+            .collect();
+        expected.sort();
+        let found: Vec<_> = quad.nearest_filtered(origin, |_, label| *label == 0)
+            .inspect(|(_, label)| assert_eq!(**label, 0))
+            .map(|(p, _)| dist(p))
+            .collect();
+        assert_eq!(expected, found);
+    }
+}
+
+#[test]
+fn knn_join_matches_nearest() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10 {
+        let mut a = QuadTree::new();
+        let mut b = QuadTree::new();
+        for p in generate_random_points(rng.gen_range(1..500), 1e2) {
+            a.insert(p);
+        }
+        for p in generate_random_points(rng.gen_range(1..500), 1e2) {
+            b.insert(p);
+        }
+        let k = rng.gen_range(1..20);
+        let dist = |a: &(f32, f32), b: &(f32, f32)| u32::max(
+            u32::abs_diff(ordered_float(a.0), ordered_float(b.0)),
+            u32::abs_diff(ordered_float(a.1), ordered_float(b.1)),
+        );
+        let mut count = 0;
+        for (p, neighbors) in knn_join(&a, &b, k) {
+            let expected: Vec<_> = b.nearest(*p).take(k).map(|q| dist(p, q)).collect();
+            let found: Vec<_> = neighbors.iter().map(|q| dist(p, q)).collect();
+            assert_eq!(expected, found);
+            count += 1;
+        }
+        assert_eq!(count, a.query((0, 0), (u32::MAX, u32::MAX)).count());
+    }
+}
+
+#[test]
+fn distance_join() {
+    let mut rng = rand::thread_rng();
+    for metric in [Metric::Chebyshev, Metric::Euclidean] {
+        for _ in 0..10 {
+            let points_a = generate_random_points(rng.gen_range(1..500), 1e2);
+            let points_b = generate_random_points(rng.gen_range(1..500), 1e2);
+            let mut a = QuadTree::new();
+            let mut b = QuadTree::new();
+            points_a.iter().for_each(|p| a.insert(*p));
+            points_b.iter().for_each(|p| b.insert(*p));
+            let eps = rng.gen_range(1e-1..1e1);
+
+            let mut expected = Vec::new();
+            for p in &points_a {
+                for q in &points_b {
+                    if metric.within(p, q, eps) { expected.push((*p, *q)) };
+                }
+            }
+            let mut found: Vec<_> = within_distance_join(&a, &b, eps, metric)
+                .map(|(p, q)| (*p, *q))
+                .collect();
+            let key = |t: &((f32, f32), (f32, f32))| [t.0.0, t.0.1, t.1.0, t.1.1].map(ordered_float);
+            expected.sort_by_key(key);
+            found.sort_by_key(key);
+            assert_eq!(expected, found);
+            assert_eq!(within_distance_join(&a, &b, -1.0, metric).count(), 0);
+            assert_eq!(within_distance_join(&a, &b, f32::NAN, metric).count(), 0);
+        }
+    }
+}
+
+#[test]
+fn self_join_counts() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10 {
+        let mut quad = QuadTree::new();
+        for p in generate_random_points(rng.gen_range(1..1000), 1e2) {
+            quad.insert(p);
+        }
+        let distance = rng.gen_range(1e-1..1e1);
+        let expected: Vec<_> = quad.iter()
+            .map(|(p, _)| quad.count_within_distance(p, distance))
+            .collect();
+        assert_eq!(expected, quad.neighbor_counts(distance));
+
+        let pairs = quad.all_pairs_within(distance).count();
+        let counted: usize = expected.iter().map(|c| c - 1).sum();
+        assert_eq!(pairs * 2, counted);
+        assert!(quad.all_pairs_within(distance).all(|(p, q)| p != q && AABB(*q, distance).contains(p)));
+        for invalid in [-1.0, f32::NAN] {
+            assert_eq!(quad.neighbor_counts(invalid), vec![0; quad.len()]);
+            assert_eq!(quad.all_pairs_within(invalid).count(), 0);
+        }
+    }
+}
+
+#[test]
+fn dbscan_matches_brute_force() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10 {
+        let mut quad = QuadTree::new();
+        for p in generate_random_points(rng.gen_range(1..1000), 1e2) {
+            quad.insert(p);
+        }
+        let eps = rng.gen_range(1e0..5e0);
+        let min_pts = rng.gen_range(1..8);
+
+        // Textbook DBSCAN over the points in the same order
+        let points: Vec<_> = quad.iter().map(|(p, _)| *p).collect();
+        let neighbors = |i: usize| -> Vec<usize> {
+            (0..points.len())
+                .filter(|&j| Metric::Euclidean.within(&points[i], &points[j], eps))
+                .collect()
+        };
+        let mut expected = vec![None; points.len()];
+        let mut visited = vec![false; points.len()];
+        let mut cluster = 0;
+        for i in 0..points.len() {
+            if visited[i] { continue };
+            visited[i] = true;
+            let found = neighbors(i);
+            if found.len() < min_pts { continue };
+            expected[i] = Some(cluster);
+            let mut queue = std::collections::VecDeque::from(found);
+            while let Some(j) = queue.pop_front() {
+                if expected[j].is_none() { expected[j] = Some(cluster) };
+                if visited[j] { continue };
+                visited[j] = true;
+                let found = neighbors(j);
+                if found.len() >= min_pts { queue.extend(found) };
+            }
+            cluster += 1;
+        }
+
+        assert_eq!(expected, cluster::dbscan(&quad, eps, min_pts));
+        assert_eq!(vec![None; quad.len()], cluster::dbscan(&quad, -1.0, min_pts));
+        assert_eq!(vec![None; quad.len()], cluster::dbscan(&quad, f32::NAN, min_pts));
+    }
+}
+
+#[test]
+fn clusters_by_cell() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let points = generate_random_points(1000, 1e2);
+    points.iter().for_each(|p| quad.insert(*p));
+    for level in [0, 1, 3, 8, 16, 32] {
+        let clusters = quad.cluster_at_level(level);
+        assert_eq!(clusters.iter().map(|c| c.count).sum::<usize>(), quad.len());
+        assert!(clusters.windows(2).all(|w| w[0].cell < w[1].cell));
+        for _ in 0..10 {
+            let p = points[rng.gen_range(0..points.len())];
+            let z = morton_2(ordered_float(p.0), ordered_float(p.1));
+            let prefix = z.checked_shr(64 - 2 * level).unwrap_or(0);
+            let members: Vec<_> = points.iter()
+                .filter(|q| morton_2(ordered_float(q.0), ordered_float(q.1)).checked_shr(64 - 2 * level).unwrap_or(0) == prefix)
+                .collect();
+            let cluster = clusters.iter().find(|c| c.cell.prefix() == prefix).unwrap();
+            assert_eq!(cluster.count, members.len());
+            assert_eq!(cluster.min.0, members.iter().map(|q| q.0).fold(f32::INFINITY, f32::min));
+            assert_eq!(cluster.max.1, members.iter().map(|q| q.1).fold(f32::NEG_INFINITY, f32::max));
+            let mean = members.iter().map(|q| q.0 as f64).sum::<f64>() / members.len() as f64;
+            assert!((cluster.centroid.0 as f64 - mean).abs() < 1e-3);
+        }
+    }
+}
+
+#[test]
+fn cell_navigation() {
+    let mut rng = rand::thread_rng();
+    assert_eq!(Cell::root().key_range(), (0, u64::MAX));
+    assert_eq!(Cell::root().parent(), None);
+    let infinite = ((f32::NEG_INFINITY, f32::NEG_INFINITY), (f32::INFINITY, f32::INFINITY));
+    assert_eq!(Cell::root().bounds(), infinite);
+    let corner = Cell::from_key(u64::MAX, 40);
+    assert_eq!((corner.level(), corner.prefix()), (Cell::MAX_LEVEL, u64::MAX));
+    assert_eq!(corner.bounds(), ((f32::INFINITY, f32::INFINITY), (f32::INFINITY, f32::INFINITY)));
+    let positive = Cell::from_coordinates(1, 1, 1).bounds();
+    assert_eq!((positive.0, positive.1), ((0.0, 0.0), (f32::INFINITY, f32::INFINITY)));
+    assert_eq!(Cell::from_coordinates(3, 3, 40).level(), Cell::MAX_LEVEL);
+    for _ in 0..1000 {
+        let key = rng.gen();
+        let level = rng.gen_range(1..32);
+        let cell = Cell::from_key(key, level);
+        assert!(cell.contains(key));
+        let (min, max) = cell.key_range();
+        assert!(min <= key && key <= max);
+        assert_eq!(cell, Cell::from_key(min, level));
+        assert_eq!(cell, Cell::from_key(max, level));
+        assert!(!cell.contains(max.wrapping_add(1)));
+
+        let parent = cell.parent().unwrap();
+        assert!(parent.contains(key));
+        let children = parent.children().unwrap();
+        assert!(children.contains(&cell));
+        assert_eq!(children[0].key_range().0, parent.key_range().0);
+        assert_eq!(children[3].key_range().1, parent.key_range().1);
+        assert!(children.windows(2).all(|w| w[0].key_range().1 + 1 == w[1].key_range().0));
+
+        let (x, y) = cell.coordinates();
+        assert_eq!(cell, Cell::from_coordinates(x, y, level));
+        for n in cell.neighbors() {
+            let (nx, ny) = n.coordinates();
+            assert_eq!(n.level(), level);
+            assert_eq!(u32::max(nx.abs_diff(x), ny.abs_diff(y)), 1);
+        }
+    }
+    let mut quad = QuadTree::new();
+    generate_random_points(1000, 1e2).into_iter().for_each(|p| quad.insert(p));
+    for cluster in quad.cluster_at_level(10) {
+        let (min, max) = cluster.cell.bounds();
+        assert_eq!(quad.query_cell(&cluster.cell).count(), cluster.count);
+        assert!(quad.query_cell(&cluster.cell).all(|(p, _)| min.0 <= p.0 && p.0 <= max.0 && min.1 <= p.1 && p.1 <= max.1));
+    }
+}
+
+#[test]
+fn histogram_matches_binning() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let points = generate_random_points(5000, 1e2);
+    points.iter().for_each(|p| quad.insert(*p));
+    for _ in 0..20 {
+        let min = (rng.gen_range(0.0..50.0), rng.gen_range(0.0..50.0));
+        let rect = (min, (min.0 + rng.gen_range(1.0..50.0), min.1 + rng.gen_range(1.0..50.0)));
+        let (cols, rows) = (rng.gen_range(1..40), rng.gen_range(1..40));
+        let histogram = quad.histogram(rect, cols, rows);
+
+        let mut expected = Histogram::new(rect, cols, rows);
+        for p in &points {
+            if let Some((col, row)) = expected.bin(p) {
+                expected.counts[row * cols + col] += 1;
+            }
+        }
+        assert_eq!(expected, histogram);
+        assert_eq!(histogram.to_2d().len(), rows);
+        assert_eq!(histogram.counts.iter().sum::<usize>(), quad.query_float(rect.0, rect.1).count());
+    }
+}
+
+#[test]
+fn counted_map_matches_btree() {
+    use std::collections::BTreeMap;
+    let mut rng = rand::thread_rng();
+    for _ in 0..20 {
+        let mut counted = CountedMap::new();
+        let mut btree = BTreeMap::new();
+        for i in 0..rng.gen_range(0..20000) {
+            let key = rng.gen_range(0..50000u64);
+            assert_eq!(Store::insert(&mut counted, key, i), btree.insert(key, i));
+        }
+        assert_eq!(Store::len(&counted), btree.len());
+        for _ in 0..100 {
+            let a = rng.gen_range(0..50000u64);
+            let b = rng.gen_range(a..50000u64);
+            assert_eq!(counted.rank(a), btree.range(..a).count());
+            assert_eq!(counted.count(a..=b), btree.range(a..=b).count());
+            assert_eq!(counted.count(a..b), btree.range(a..b).count());
+            let mut x = Store::range(&counted, a..=b);
+            let mut y = btree.range(a..=b);
+            loop {
+                let step = if rng.gen() { (x.next(), y.next()) } else { (x.next_back(), y.next_back()) };
+                assert_eq!(step.0, step.1);
+                if step.0.is_none() { break };
+            }
+        }
+        assert!(Store::range(&counted, ..).map(|(k, _)| k).eq(btree.keys()));
+    }
+}
+
+#[test]
+fn count_in_range() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let mut counted = CountedQuadTree::default();
+    for p in generate_random_points(5000, 1e2) {
+        quad.insert(p);
+        counted.insert(p);
+    }
+    for _ in 0..100 {
+        let rect = random_rect(&mut rng);
+        let expected = quad.query_float(rect.0, rect.1).count();
+        assert_eq!(expected, quad.count_in_range(rect));
+        assert_eq!(expected, counted.count_in_range(rect));
+        assert_eq!(expected, counted.query_float(rect.0, rect.1).count());
+    }
+}
+
+#[test]
+fn aggregate_in_range() {
+    let mut rng = rand::thread_rng();
+    let mut quad = AugmentedQuadTree::<f32, Stats>::default();
+    for p in generate_random_points(5000, 1e2) {
+        quad.insert_value(p, rng.gen_range(-1e1..1e1));
+    }
+    for _ in 0..100 {
+        let rect = random_rect(&mut rng);
+        let min = (ordered_float(rect.0.0), ordered_float(rect.0.1));
+        let max = (ordered_float(rect.1.0), ordered_float(rect.1.1));
+        let values: Vec<f64> = quad.query_cursor(min, max)
+            .map(|(_, v)| *v as f64)
+            .collect();
+        let stats = quad.aggregate_in_range(rect);
+        assert_eq!(values.len(), stats.count);
+        assert!((values.iter().sum::<f64>() - stats.sum).abs() < 1e-6);
+        assert_eq!(values.iter().cloned().fold(f64::INFINITY, f64::min), stats.min);
+        assert_eq!(values.iter().cloned().fold(f64::NEG_INFINITY, f64::max), stats.max);
+    }
+}
+
+#[test]
+fn top_k_in_range() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let mut augmented = AugmentedQuadTree::<f32, Max>::default();
+    for p in generate_random_points(5000, 1e2) {
+        let rating = rng.gen_range(0.0..5.0);
+        quad.insert_value(p, rating);
+        augmented.insert_value(p, rating);
+    }
+    for _ in 0..100 {
+        let rect = random_rect(&mut rng);
+        let k = rng.gen_range(0..30);
+        let min = (ordered_float(rect.0.0), ordered_float(rect.0.1));
+        let max = (ordered_float(rect.1.0), ordered_float(rect.1.1));
+        let mut expected: Vec<f32> = quad.query_cursor(min, max).map(|(_, v)| *v).collect();
+        expected.sort_by(|a, b| b.total_cmp(a));
+        expected.truncate(k);
+        let ratings = |top: Vec<(&(f32, f32), &f32)>| top.into_iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        assert_eq!(expected, ratings(quad.top_k_in_range(rect, k, |v| *v as f64)));
+        assert_eq!(expected, ratings(augmented.top_k_in_range(rect, k, |v| *v as f64)));
+        assert_eq!(expected, ratings(augmented.top_k_by_measure(rect, k)));
+        let mut lowest: Vec<f32> = quad.query_cursor(min, max).map(|(_, v)| *v).collect();
+        lowest.sort_by(f32::total_cmp);
+        lowest.truncate(k);
+        assert_eq!(lowest, ratings(augmented.top_k_in_range(rect, k, |v| -*v as f64)));
+    }
+}
+
+#[test]
+fn sample_in_range() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut quad = QuadTree::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            quad.insert((i as f32, j as f32));
+        }
+    }
+    let rect = ((2.0, 2.0), (6.0, 3.0));
+    let sample = |seed| quad.sample_in_range(rect, 4, &mut StdRng::seed_from_u64(seed));
+    assert_eq!(sample(7), sample(7));
+    assert_eq!(10, quad.sample_in_range(rect, 20, &mut StdRng::seed_from_u64(0)).len());
+    let mut hits = std::collections::HashMap::new();
+    for seed in 0..10000 {
+        let points = sample(seed);
+        assert_eq!(4, points.len());
+        for (p, _) in points {
+            assert!((2.0..=6.0).contains(&p.0) && (2.0..=3.0).contains(&p.1));
+            *hits.entry((p.0 as u32, p.1 as u32)).or_insert(0) += 1;
+        }
+    }
+    assert_eq!(10, hits.len());
+    assert!(hits.values().all(|&h| (3600..4400).contains(&h)));
+}
+
+#[test]
+fn order_statistics() {
+    let mut quad = QuadTree::new();
+    let mut counted = CountedQuadTree::default();
+    for p in generate_random_points(3000, 1e2) {
+        quad.insert(p);
+        counted.insert(p);
+    }
+    let keys: Vec<u64> = (0..quad.len()).map(|i| quad.select(i).unwrap().0).collect();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(None, quad.select(keys.len()).map(|s| s.0));
+    for (i, &k) in keys.iter().enumerate().step_by(7) {
+        assert_eq!(i, quad.rank(k));
+        assert_eq!(i, counted.rank(k));
+        assert_eq!(i + 1, counted.rank(k + 1));
+        assert_eq!(Some(k), counted.select(i).map(|s| s.0));
+    }
+    let splits = counted.quantile_keys(7);
+    assert_eq!(splits, quad.quantile_keys(7));
+    let bounds: Vec<usize> = std::iter::once(0)
+        .chain(splits.iter().map(|&k| quad.rank(k)))
+        .chain(std::iter::once(quad.len()))
+        .collect();
+    let part = quad.len() / 7;
+    assert!(bounds.windows(2).all(|w| w[1] - w[0] == part || w[1] - w[0] == part + 1));
+
+    // Fewer points than parts repeat ranks
+    let (mut quad, mut counted) = (QuadTree::new(), CountedQuadTree::default());
+    for p in generate_random_points(3, 1e2) {
+        quad.insert(p);
+        counted.insert(p);
+    }
+    assert_eq!(counted.quantile_keys(7), quad.quantile_keys(7));
+    assert_eq!(QuadTree::<()>::new().quantile_keys(3), vec![u64::MAX; 2]);
+}
+
+#[test]
+fn morton_batches() {
+    let mut rng = rand::thread_rng();
+    let points: Vec<(u32, u32)> = (0..1001).map(|_| (rng.gen(), rng.gen())).collect();
+    let mut keys = vec![0; points.len()];
+    morton_2_batch(&points, &mut keys);
+    assert!(points.iter().zip(&keys).all(|(p, &k)| morton_2(p.0, p.1) == k));
+    let mut decoded = vec![(0, 0); keys.len()];
+    morton_reverse_2_batch(&keys, &mut decoded);
+    assert_eq!(points, decoded);
+
+    let points: Vec<Point4> = (0..1001).map(|_| (rng.gen(), rng.gen(), rng.gen(), rng.gen())).collect();
+    morton_4_batch(&points, &mut keys);
+    assert!(points.iter().zip(&keys).all(|(p, &k)| morton_4(p.0, p.1, p.2, p.3) == k));
+    let mut decoded = vec![(0, 0, 0, 0); keys.len()];
+    morton_reverse_4_batch(&keys, &mut decoded);
+    assert_eq!(points, decoded);
+}
+
+#[test]
+fn hilbert_curve() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..1000 {
+        let (x, y): (u32, u32) = (rng.gen(), rng.gen());
+        assert_eq!((x, y), hilbert_reverse_2(hilbert_2(x, y)));
+        let p: Point4 = rng.gen();
+        assert_eq!(p, hilbert_reverse_4(hilbert_4(p.0, p.1, p.2, p.3)));
+        // Consecutive keys are neighbours
+        let h: u64 = rng.gen_range(0..u64::MAX);
+        let (a, b) = (hilbert_reverse_2(h), hilbert_reverse_2(h + 1));
+        assert_eq!(1, a.0.abs_diff(b.0) + a.1.abs_diff(b.1));
+        let (a, b) = (hilbert_reverse_4(h), hilbert_reverse_4(h + 1));
+        assert_eq!(1, a.0.abs_diff(b.0) + a.1.abs_diff(b.1) + a.2.abs_diff(b.2) + a.3.abs_diff(b.3));
+    }
+    // The first 64 x 64 keys fill the corner square, check the skipping against every key
+    for _ in 0..100 {
+        let (x0, y0) = (rng.gen_range(0..64), rng.gen_range(0..64));
+        let (x1, y1) = (rng.gen_range(x0..64), rng.gen_range(y0..64));
+        let hi = HilbertIndexer::<2>::new((x0, y0), (x1, y1));
+        let inside: Vec<u64> = (0..64 * 64).filter(|&h| {
+            let (x, y) = hilbert_reverse_2(h);
+            x0 <= x && x <= x1 && y0 <= y && y <= y1
+        }).collect();
+        assert_eq!((inside[0], inside[inside.len() - 1]), *hi.bounds());
+        let skipped: Vec<u64> = std::iter::successors(Some(inside[0]), |&h| hi.next_hilbert_index(h)).collect();
+        assert_eq!(inside, skipped);
+    }
+}
+
+#[test]
+fn hilbert_quadtree() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let mut hilbert = HilbertQuadTree::default();
+    for p in generate_random_points(3000, 1e2) {
+        quad.insert(p);
+        hilbert.insert(p);
+    }
+    let dist = |p: &(f32, f32), q: (f32, f32)| f32::max((p.0 - q.0).abs(), (p.1 - q.1).abs());
+    for _ in 0..50 {
+        let (min, max) = random_rect(&mut rng);
+        let mut expected: Vec<_> = quad.query_float(min, max).collect();
+        let mut found: Vec<_> = hilbert.query_float(min, max).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(expected, found);
+
+        let q = (rng.gen_range(0.0..1e2), rng.gen_range(0.0..1e2));
+        let expected: Vec<f32> = quad.nearest(q).take(20).map(|p| dist(p, q)).collect();
+        let found: Vec<f32> = hilbert.nearest(q).take(20).map(|p| dist(p, q)).collect();
+        assert_eq!(expected, found);
+    }
+}
+
+#[test]
+fn hilbert_joins() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let mut hilbert = HilbertQuadTree::default();
+    for p in generate_random_points(1000, 1e2) {
+        quad.insert(p);
+        hilbert.insert(p);
+    }
+    let distance = rng.gen_range(1e-1..5e0);
+    // Pairs as sorted coordinate keys, with `unordered` pairs put in a fixed order
+    let sorted = |pairs: &mut dyn Iterator<Item = (&(f32, f32), &(f32, f32))>, unordered: bool| {
+        let mut keys: Vec<_> = pairs
+            .map(|(p, q)| ([p.0, p.1].map(ordered_float), [q.0, q.1].map(ordered_float)))
+            .map(|(p, q)| if unordered && p > q { (q, p) } else { (p, q) })
+            .collect();
+        keys.sort();
+        keys
+    };
+    let euclidean = Metric::Euclidean;
+    let expected = sorted(&mut within_distance_join(&quad, &quad, distance, euclidean), false);
+    assert_eq!(expected, sorted(&mut within_distance_join(&hilbert, &quad, distance, euclidean), false));
+    assert_eq!(expected, sorted(&mut within_distance_join(&quad, &hilbert, distance, euclidean), false));
+    assert_eq!(
+        sorted(&mut quad.all_pairs_within(distance), true),
+        sorted(&mut hilbert.all_pairs_within(distance), true),
+    );
+    let mut counts: Vec<_> = hilbert.iter().zip(hilbert.neighbor_counts(distance))
+        .map(|((p, _), c)| (*p, c))
+        .collect();
+    counts.sort_by_key(|(p, _)| { let (x, y) = quad.grid_point(*p); morton_2(x, y) });
+    assert_eq!(quad.neighbor_counts(distance), counts.into_iter().map(|t| t.1).collect::<Vec<_>>());
+
+    let dist = |p: &(f32, f32), q: &(f32, f32)| f32::max((p.0 - q.0).abs(), (p.1 - q.1).abs());
+    for ((p, near), (q, other)) in knn_join(&quad, &quad, 5).zip(knn_join(&quad, &hilbert, 5)) {
+        assert_eq!(p, q);
+        let near: Vec<_> = near.iter().map(|n| dist(p, n)).collect();
+        let other: Vec<_> = other.iter().map(|n| dist(q, n)).collect();
+        assert_eq!(near, other);
+    }
+
+    // Border points may join either neighbouring cluster, but noise does not depend on order
+    let noise = |points: Vec<&(f32, f32)>, labels: Vec<Option<usize>>| {
+        let mut noise: Vec<_> = points.into_iter().zip(labels)
+            .filter(|(_, l)| l.is_none())
+            .map(|(p, _)| [p.0, p.1].map(ordered_float))
+            .collect();
+        noise.sort();
+        noise
+    };
+    assert_eq!(
+        noise(quad.iter().map(|(p, _)| p).collect(), cluster::dbscan(&quad, distance, 4)),
+        noise(hilbert.iter().map(|(p, _)| p).collect(), cluster::dbscan(&hilbert, distance, 4)),
+    );
+}
+
+/// Z-order with the y bit above the x bit, standing in for a user-defined curve.
+struct YFirst;
+
+impl SpaceFillingCurve for YFirst {
+    type Box = ZOrderIndexer<2>;
+    fn encode(x: u32, y: u32) -> u64 {
+        morton_2(y, x)
+    }
+    fn decode(key: u64) -> (u32, u32) {
+        let (y, x) = morton_reverse_2(key);
+        (x, y)
+    }
+    fn new_box(min: (u32, u32), max: (u32, u32)) -> Self::Box {
+        ZOrderIndexer::<2>::new((min.1, min.0), (max.1, max.0))
+    }
+    fn key_range(b: &Self::Box) -> (u64, u64) {
+        *b.bounds()
+    }
+    fn contains(b: &Self::Box, key: u64) -> bool {
+        b.contains(key)
+    }
+    fn next_in_box(b: &Self::Box, key: u64) -> Option<u64> {
+        b.next_zorder_index(key)
+    }
+    fn cell_bounds(cell: &Cell) -> ((u32, u32), (u32, u32)) {
+        let (lo, hi) = cell.grid_bounds();
+        ((lo.1, lo.0), (hi.1, hi.0))
+    }
+}
+
+#[test]
+fn generic_curves() {
+    let mut rng = rand::thread_rng();
+    let mut quad = QuadTree::new();
+    let mut hilbert = HilbertQuadTree::default();
+    let mut custom = QuadTree::<(), BTreeMap<u64, Entry<()>>, YFirst>::default();
+    for p in generate_random_points(3000, 1e2) {
+        quad.insert(p);
+        hilbert.insert(p);
+        custom.insert(p);
+    }
+    for _ in 0..50 {
+        let rect = random_rect(&mut rng);
+        let expected = quad.query_float(rect.0, rect.1).count();
+        assert_eq!(expected, hilbert.count_in_range(rect));
+        assert_eq!(expected, custom.count_in_range(rect));
+        assert_eq!(expected, custom.query_float(rect.0, rect.1).count());
+        let histogram = quad.histogram(rect, 5, 3);
+        assert_eq!(histogram, hilbert.histogram(rect, 5, 3));
+        assert_eq!(histogram, custom.histogram(rect, 5, 3));
+    }
+}
+
+#[test]
+fn bit_schedules() {
+    let mut rng = rand::thread_rng();
+    let morton = BitSchedule::<2>::interleaved();
+    let (x, y): (u32, u32) = rng.gen();
+    assert_eq!(morton_2(x, y), morton.encode([x as u64, y as u64]));
+    assert_eq!(morton, BitSchedule::round_robin([32, 32]));
+
+    let spacetime = BitSchedule::round_robin([40, 12, 12]);
+    assert_eq!([40, 12, 12], spacetime.bits());
+    assert_eq!((1 << 28) - 1, spacetime.masks()[0] & ((1 << 28) - 1));
+    for _ in 0..1000 {
+        let point = [rng.gen_range(0..1 << 40), rng.gen_range(0..1 << 12), rng.gen_range(0..1 << 12)];
+        assert_eq!(point, spacetime.decode(spacetime.encode(point)));
+        let (value, mask): (u64, u64) = rng.gen();
+        assert_eq!(crate::morton::deposit_bits(value, mask), crate::morton::deposit_bits_portable(value, mask));
+        assert_eq!(crate::morton::extract_bits(value, mask), crate::morton::extract_bits_portable(value, mask));
+    }
+
+    // Small enough to check the skipping against every key
+    let schedule = BitSchedule::from_pattern(&[0, 0, 1, 2, 0, 1, 2, 0, 0, 1, 0]);
+    let bits = schedule.bits();
+    for _ in 0..200 {
+        let mut min = [0; 3];
+        let mut max = [0; 3];
+        for d in 0..3 {
+            min[d] = rng.gen_range(0..1 << bits[d]);
+            max[d] = rng.gen_range(min[d]..1 << bits[d]);
+        }
+        let zi = schedule.indexer(min, max);
+        let inside: Vec<u64> = (0..1 << 11).filter(|&k| {
+            let p = schedule.decode(k);
+            (0..3).all(|d| min[d] <= p[d] && p[d] <= max[d])
+        }).collect();
+        assert!((0..1 << 11).all(|k| zi.contains(k) == inside.contains(&k)));
+        let skipped: Vec<u64> = std::iter::successors(Some(inside[0]), |&k| zi.next_zorder_index(k)).collect();
+        assert_eq!(inside, skipped);
+    }
+}
+
+#[test]
+fn quantized_tree() {
+    let mut rng = rand::thread_rng();
+    let quantizer = Quantizer::new(((0.0, 0.0), (1e2, 1e2)), 1e-3);
+    assert_eq!((0, 0), quantizer.quantize((-5.0, 0.0)));
+    assert_eq!((1000, 99999), quantizer.quantize((1.0005, 99.9995)));
+    // Many points to a step, and clamped ones piling up on the border
+    let coarse = Quantizer::new(((0.0, 0.0), (1e2, 1e2)), 1.0);
+    let mut quad = QuadTree::new();
+    let mut quantized = QuadTree::quantized(coarse);
+    for p in generate_random_points(5000, 1.1e2).into_iter().chain([(1.1, 1.1), (1.2, 1.2), (-5.0, 10.0), (-7.0, 10.0)]) {
+        quad.insert(p);
+        quantized.insert(p);
+    }
+    assert_eq!(quad.len(), quantized.len());
+    let mut cell = QuadTree::quantized(coarse);
+    assert_eq!(cell.try_insert_value((1.1, 1.1), 'a'), Ok(None));
+    assert_eq!(cell.insert_value((1.2, 1.2), 'b'), None);
+    assert_eq!(cell.insert_value((1.1, 1.1), 'c'), Some('a'));
+    assert_eq!(cell.insert_value((-5.0, 10.0), 'd'), None);
+    assert_eq!(cell.insert_value((-7.0, 10.0), 'e'), None);
+    let mut found: Vec<_> = cell.iter().map(|(p, v)| (*p, *v)).collect();
+    found.sort_by_key(|t| t.1);
+    assert_eq!(found, vec![((1.2, 1.2), 'b'), ((1.1, 1.1), 'c'), ((-5.0, 10.0), 'd'), ((-7.0, 10.0), 'e')]);
+    assert_eq!(cell.query_float((1.0, 1.0), (1.15, 1.15)).collect::<Vec<_>>(), vec![&(1.1, 1.1)]);
+    assert_eq!(cell.nearest((1.5, 1.5)).take(2).count(), 2);
+    assert_eq!(cell.count_within_distance(&(-6.0, 10.0), 1.0), 2);
+    for _ in 0..50 {
+        let rect = random_rect(&mut rng);
+        let mut expected: Vec<_> = quad.query_float(rect.0, rect.1).collect();
+        let mut found: Vec<_> = quantized.query_float(rect.0, rect.1).collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(expected, found);
+        assert_eq!(expected.len(), quantized.count_in_range(rect));
+        assert_eq!(quad.histogram(rect, 4, 3), quantized.histogram(rect, 4, 3));
+        assert_eq!(quad.count_within_distance(&rect.0, 5.0), quantized.count_within_distance(&rect.0, 5.0));
+    }
+    // Chebyshev distance in grid steps
+    let steps = |p: &(f32, f32), q: &(f32, f32)| {
+        let (p, q) = (coarse.quantize(*p), coarse.quantize(*q));
+        u32::max(p.0.abs_diff(q.0), p.1.abs_diff(q.1))
+    };
+    let found: Vec<u32> = quantized.nearest((5e1, 5e1)).map(|p| steps(p, &(5e1, 5e1))).collect();
+    assert_eq!(found.len(), quantized.len());
+    assert!(found.windows(2).all(|w| w[0] <= w[1]));
+    for (p, near) in knn_join(&quad, &quantized, 3).take(100) {
+        let kth = near.iter().map(|q| steps(p, q)).max();
+        let closer = quantized.iter().filter(|(q, _)| Some(steps(p, q)) < kth).count();
+        assert!(near.len() == 3 && closer < 3);
+    }
+    let joined = within_distance_join(&quad, &quad, 0.5, Metric::Euclidean).count();
+    assert_eq!(joined, within_distance_join(&quad, &quantized, 0.5, Metric::Euclidean).count());
+    assert_eq!(joined, within_distance_join(&quantized, &quad, 0.5, Metric::Euclidean).count());
+}
+
+#[test]
+fn ordered_transforms() {
+    let mut rng = rand::thread_rng();
+    let mut i16s: Vec<i16> = (0..1000).map(|_| rng.gen()).collect();
+    let mut i32s: Vec<i32> = (0..1000).map(|_| rng.gen()).collect();
+    let mut i64s: Vec<i64> = (0..1000).map(|_| rng.gen()).collect();
+    let mut f64s: Vec<f64> = (0..1000).map(|_| rng.gen_range(-1e9..1e9)).collect();
+    i16s.extend([i16::MIN, -1, 0, 1, i16::MAX]);
+    i32s.extend([i32::MIN, -1, 0, 1, i32::MAX]);
+    i64s.extend([i64::MIN, -1, 0, 1, i64::MAX]);
+    f64s.extend([f64::NEG_INFINITY, f64::MIN, -1e-300, -0.0, 0.0, 1e-300, f64::MAX, f64::INFINITY]);
+    i16s.sort();
+    i32s.sort();
+    i64s.sort();
+    f64s.sort_by(f64::total_cmp);
+    for w in i16s.windows(2) {
+        assert!(ordered_i16(w[0]) <= ordered_i16(w[1]));
+        assert_eq!(ordered_i16_reverse(ordered_i16(w[0])), w[0]);
+    }
+    for w in i32s.windows(2) {
+        assert!(ordered_i32(w[0]) <= ordered_i32(w[1]));
+        assert_eq!(ordered_i32_reverse(ordered_i32(w[0])), w[0]);
+    }
+    for w in i64s.windows(2) {
+        assert!(ordered_i64(w[0]) <= ordered_i64(w[1]));
+        assert_eq!(ordered_i64_reverse(ordered_i64(w[0])), w[0]);
+    }
+    for w in f64s.windows(2) {
+        assert!(ordered_f64(w[0]) < ordered_f64(w[1]));
+        assert_eq!(ordered_f64_reverse(ordered_f64(w[0])).to_bits(), w[0].to_bits());
+    }
+    assert_eq!(ordered_i16_reverse(ordered_i16(i16::MAX)), i16::MAX);
+    assert_eq!(ordered_i32_reverse(ordered_i32(i32::MAX)), i32::MAX);
+    assert_eq!(ordered_i64_reverse(ordered_i64(i64::MAX)), i64::MAX);
+    assert_eq!(ordered_f64_reverse(ordered_f64(f64::INFINITY)), f64::INFINITY);
+}
+
+#[test]
+fn morton_arithmetic() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..1000 {
+        let (x, y, d): (u32, u32, u32) = (rng.gen(), rng.gen(), rng.gen_range(0..4));
+        let z = morton_2(x, y);
+        assert_eq!(morton_add_x(z, d), morton_2(x.wrapping_add(d), y));
+        assert_eq!(morton_sub_x(z, d), morton_2(x.wrapping_sub(d), y));
+        assert_eq!(morton_add_y(z, d), morton_2(x, y.wrapping_add(d)));
+        assert_eq!(morton_sub_y(z, d), morton_2(x, y.wrapping_sub(d)));
+        let d: u32 = rng.gen();
+        assert_eq!(morton_add_x(z, d), morton_2(x.wrapping_add(d), y));
+        assert_eq!(morton_sub_y(z, d), morton_2(x, y.wrapping_sub(d)));
+
+        let p: [u16; 4] = rng.gen();
+        let d: u16 = rng.gen();
+        let z = morton_4(p[0], p[1], p[2], p[3]);
+        for axis in 0..4 {
+            let (mut up, mut down) = (p, p);
+            up[axis] = up[axis].wrapping_add(d);
+            down[axis] = down[axis].wrapping_sub(d);
+            assert_eq!(morton_4_add(z, axis, d), morton_4(up[0], up[1], up[2], up[3]));
+            assert_eq!(morton_4_sub(z, axis, d), morton_4(down[0], down[1], down[2], down[3]));
+        }
+    }
+    assert_eq!(morton_add_x(morton_2(u32::MAX, 7), 1), morton_2(0, 7));
+    assert_eq!(morton_sub_y(morton_2(7, 0), 1), morton_2(7, u32::MAX));
+    assert_eq!(Cell::from_coordinates(0, 0, 3).neighbors().len(), 3);
+    assert_eq!(Cell::from_coordinates(7, 3, 3).neighbors().len(), 5);
+    assert_eq!(Cell::from_coordinates(3, 3, 3).neighbors().len(), 8);
+    assert!(Cell::root().neighbors().is_empty());
+}
+
+#[test]
+fn key_ranges() {
+    let mut rng = rand::thread_rng();
+    let schedule = BitSchedule::<2>::from_pattern(&[0, 0, 1, 0, 1, 1, 0, 1, 0, 1, 1, 0]);
+    for i in 0..200 {
+        let (a, b): ([u64; 2], [u64; 2]) = ([rng.gen_range(0..64), rng.gen_range(0..64)], [rng.gen_range(0..64), rng.gen_range(0..64)]);
+        let (min, max) = ([a[0].min(b[0]), a[1].min(b[1])], [a[0].max(b[0]), a[1].max(b[1])]);
+        let zi = if i % 2 == 0 {
+            ZOrderIndexer::<2>::new((min[0] as u32, min[1] as u32), (max[0] as u32, max[1] as u32))
+        } else {
+            schedule.indexer(min, max)
+        };
+        let (first, last) = *zi.bounds();
+        let runs = (first..=last).filter(|&z| zi.contains(z) && (z == first || !zi.contains(z - 1))).count();
+        let exact = zi.key_ranges(usize::MAX);
+        assert_eq!(exact.len(), runs);
+        assert!(exact.iter().all(|&(lo, hi)| (lo..=hi).all(|z| zi.contains(z))));
+        for max_ranges in [1, 2, 3, 8] {
+            let ranges = zi.key_ranges(max_ranges);
+            assert!(ranges.len() <= max_ranges);
+            assert!(ranges.windows(2).all(|w| w[0].1 < w[1].0));
+            assert!((first..=last).filter(|&z| zi.contains(z)).all(|z| ranges.iter().any(|r| r.0 <= z && z <= r.1)));
+            let covered: u64 = ranges.iter().map(|r| r.1 - r.0 + 1).sum();
+            assert!(covered <= last - first + 1);
+        }
+    }
+    assert_eq!(ZOrderIndexer::<2>::new((0, 0), (u32::MAX, u32::MAX)).key_ranges(1), vec![(0, u64::MAX)]);
+    assert_eq!(ZOrderIndexer::<2>::new((5, 9), (5, 9)).key_ranges(4), vec![(morton_2(5, 9), morton_2(5, 9))]);
+}
+
+#[test]
+fn inverted_bounds() {
+    assert_eq!(ZOrderIndexer::<2>::try_new((5, 1), (4, 9)).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(ZOrderIndexer::<2>::try_new((1, 5), (4, 3)).err(), Some(InvertedBounds { axis: 1 }));
+    assert_eq!(ZOrderIndexer::<4>::try_new(((0, 0), (7, 0)), ((9, 9), (6, 9))).err(), Some(InvertedBounds { axis: 2 }));
+    assert_eq!(ZOrderIndexer::<2>::try_from_morton(morton_2(0, 9), morton_2(3, 2)).err(), Some(InvertedBounds { axis: 1 }));
+    let schedule = BitSchedule::<2>::from_pattern(&[1, 1, 0, 1, 0]);
+    assert_eq!(ZOrderIndexer::try_with_schedule(&schedule, schedule.encode([3, 0]), schedule.encode([1, 7])).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(InvertedBounds { axis: 1 }.to_string(), "min is greater than max on axis 1");
+
+    let zi = ZOrderIndexer::<2>::try_new((1, 3), (4, 9)).unwrap();
+    assert_eq!(ZOrderIndexer::<2>::new_normalized((4, 3), (1, 9)).bounds(), zi.bounds());
+    assert_eq!(ZOrderIndexer::<2>::new_normalized((1, 9), (4, 3)).bounds(), zi.bounds());
+    assert_eq!(ZOrderIndexer::<2>::from_morton_normalized(morton_2(4, 9), morton_2(1, 3)).bounds(), zi.bounds());
+    assert_eq!(ZOrderIndexer::with_schedule_normalized(&schedule, schedule.encode([3, 0]), schedule.encode([1, 7])).bounds(),
+        &(schedule.encode([1, 0]), schedule.encode([3, 7])));
+
+    let mut quad = QuadTree::new();
+    generate_random_points(1000, 1e2).into_iter().for_each(|p| quad.insert(p));
+    let (min, max) = ((ordered_float(10.0), ordered_float(20.0)), (ordered_float(50.0), ordered_float(60.0)));
+    assert_eq!(quad.try_query(min, max).unwrap().count(), quad.query(min, max).count());
+    assert_eq!(quad.try_query(max, min).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(quad.try_query((min.0, max.1), (max.0, min.1)).err(), Some(InvertedBounds { axis: 1 }));
+    let count = quad.query(min, max).count();
+    assert_eq!(quad.query_normalized(max, min).count(), count);
+    assert_eq!(quad.query_normalized((min.0, max.1), (max.0, min.1)).count(), count);
+    assert_eq!(quad.try_query_float((50.0, 20.0), (10.0, 60.0)).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(quad.try_query_float((10.0, 60.0), (50.0, 20.0)).err(), Some(InvertedBounds { axis: 1 }));
+    assert_eq!(quad.try_query_float((10.0, 20.0), (50.0, 60.0)).unwrap().count(), count);
+    assert_eq!(quad.query_float_normalized((50.0, 60.0), (10.0, 20.0)).count(), count);
+    assert!(quad.top_k_in_range(((50.0, 60.0), (10.0, 20.0)), 3, |_| 0.0).is_empty());
+    let token = QueryToken(0);
+    assert_eq!(quad.try_query_from(token, max, min).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(quad.try_query_from(token, min, max).unwrap().count(), quad.query_from(token, min, max).count());
+
+    let mut hilbert = HilbertQuadTree::default();
+    let mut quantized = QuadTree::quantized(Quantizer::new(((0.0, 0.0), (1e2, 1e2)), 1e-2));
+    for p in quad.iter().map(|(p, _)| *p) {
+        hilbert.insert(p);
+        let _ = quantized.try_insert(p);
+    }
+    assert_eq!(hilbert.try_query(max, min).err(), Some(InvertedBounds { axis: 0 }));
+    assert_eq!(hilbert.query_normalized(max, min).count(), count);
+    assert_eq!(quantized.try_query_float((10.0, 60.0), (50.0, 20.0)).err(), Some(InvertedBounds { axis: 1 }));
+    assert_eq!(quantized.query_float_normalized((50.0, 60.0), (10.0, 20.0)).count(), quantized.query_float((10.0, 20.0), (50.0, 60.0)).count());
+}
+
+#[test]
+fn gapped_schedules() {
+    let schedule = BitSchedule::from_masks([0b0101, 0b1000]);
+    let zi = schedule.indexer([0, 0], [3, 1]);
+    assert_eq!(zi.next_zorder_index(0b1), Some(0b100));
+    for masks in [[0b0101, 0b1000], [0b1_0010_0100, 0b0100_1001], [0b1100_0000, 0b0000_0011]] {
+        let schedule = BitSchedule::from_masks(masks);
+        let used = masks[0] | masks[1];
+        let size = schedule.bits().map(|b| 1u64 << b);
+        for (min, max) in [([0, 0], [size[0] - 1, size[1] - 1]), ([1, 0], [2, 1]), ([0, 1], [size[0] - 2, size[1] - 1])] {
+            let zi = schedule.indexer(min, max);
+            let keys: Vec<u64> = (0..=used).filter(|z| z & !used == 0 && zi.contains(*z)).collect();
+            for z in (0..=used).filter(|z| z & !used == 0) {
+                assert_eq!(zi.next_zorder_index(z), keys.iter().copied().find(|k| *k > z), "{masks:?} {min:?} {max:?} {z}");
+            }
+        }
+    }
+}
@@ -4,10 +4,10 @@ use rand::Rng;
 use std::ops::Range;
 
 // A 4D point is represented as a tuple (x, y, z, w)
-type Point4D = (i32, i32, i32, i32);
+type Point4D = (i16, i16, i16, i16);
 
 // Generates random 4D points within a specified range.
-fn generate_random_points(count: usize, bounds: Range<i32>) -> Vec<Point4D> {
+fn generate_random_points(count: usize, bounds: Range<i16>) -> Vec<Point4D> {
     let mut rng = rand::thread_rng();
     (0..count)
         .map(|_| {
@@ -22,15 +22,19 @@ fn generate_random_points(count: usize, bounds: Range<i32>) -> Vec<Point4D> {
 }
 
 // Checks if a given 4D point is within the specified range in each dimension.
-fn is_point_in_range(point: &Point4D, x_range: &Range<i32>, y_range: &Range<i32>, z_range: &Range<i32>, w_range: &Range<i32>) -> bool {
+fn is_point_in_range(point: &Point4D, x_range: &Range<i16>, y_range: &Range<i16>, z_range: &Range<i16>, w_range: &Range<i16>) -> bool {
     x_range.contains(&point.0) &&
     y_range.contains(&point.1) &&
     z_range.contains(&point.2) &&
     w_range.contains(&point.3)
 }
 
+fn mkm(p: &Point4D) -> u64 {
+    crate::morton::morton_4_signed(p.0, p.1, p.2, p.3)
+}
+
 // Performs a brute-force search for all points within the specified 4D range.
-fn brute_force_range_query(points: &[Point4D], x_range: Range<i32>, y_range: Range<i32>, z_range: Range<i32>, w_range: Range<i32>) -> Vec<Point4D> {
+fn brute_force_range_query(points: &[Point4D], x_range: Range<i16>, y_range: Range<i16>, z_range: Range<i16>, w_range: Range<i16>) -> Vec<Point4D> {
     points
         .iter()
         .filter(|&&point| is_point_in_range(&point, &x_range, &y_range, &z_range, &w_range))
@@ -43,13 +47,13 @@ fn test_4d_range_query() {
     let mut rng = rand::thread_rng();
     for _ in 0..100 {
         let num_points = 1000;
-        let bounds = 0..100; // Points will have coordinates between 0 and 1000
+        let bounds = -100..100; // Points will have coordinates between -100 and 100
         let points = generate_random_points(num_points, bounds.clone());
     
         // Generate a random 4D range
         let mut gen_range = || {
             let from = rng.gen_range(bounds.clone());
-            let length = rng.gen_range(bounds.clone());
+            let length = rng.gen_range(0..bounds.end - bounds.start);
             from..(from+length+1)
         };
         let x_range = gen_range();
@@ -60,16 +64,17 @@ fn test_4d_range_query() {
         // Perform a brute-force search
         let brute_force_results = brute_force_range_query(&points, x_range.clone(), y_range.clone(), z_range.clone(), w_range.clone());
     
-        let mkm = |p: &(i32, i32, i32, i32)| morton_4(p.0 as u16, p.1 as u16, p.2 as u16, p.3 as u16);
         // Call your Z-order range query code here and compare the results:
         let mut tree = points.clone();
         tree.sort_by_key(mkm);
         let tree = tree;
     
         use crate::morton::*;
-        let min = morton_4(x_range.start as u16, y_range.start as u16, z_range.start as u16, w_range.start as u16);
-        let max = morton_4(x_range.end as u16 - 1, y_range.end as u16 - 1, z_range.end as u16 - 1, w_range.end as u16 - 1);
-        let zi = ZOrderIndexer::<4>::from_morton(min, max);
+        let zi = ZOrderIndexer::<4>::new_signed(
+            ((x_range.start, y_range.start), (z_range.start, w_range.start)),
+            ((x_range.end - 1, y_range.end - 1), (z_range.end - 1, w_range.end - 1)),
+        );
+        let max = zi.bounds().1;
 
         let end_idx = tree.partition_point(|p| mkm(p) <= max);
         let mut results = Vec::new();
@@ -87,6 +92,34 @@ fn test_4d_range_query() {
         }
     
         // You can compare the results like this:
-        assert_eq!(brute_force_results.len(), results.len());
+        let mut results: Vec<_> = results.into_iter().cloned().collect();
+        let mut brute_force_results = brute_force_results;
+        results.sort();
+        brute_force_results.sort();
+        assert_eq!(brute_force_results, results);
+    }
+}
+
+#[test]
+fn test_4d_signed_rectangles() {
+    use crate::morton::*;
+    assert_eq!(morton_reverse_4_signed(morton_4_signed(-3, 7, i16::MIN, i16::MAX)), (-3, 7, i16::MIN, i16::MAX));
+    // Every corner of a box crossing zero on all axes, and the points just outside it
+    let zi = ZOrderIndexer::<4>::new_signed(((-2, -3), (-1, -5)), ((2, 1), (0, 4)));
+    let (min, max) = ([-2, -3, -1, -5], [2, 1, 0, 4]);
+    let mut inside = 0;
+    for x in -4..4 {
+        for y in -4..4 {
+            for z in -4..4 {
+                for w in -6..6 {
+                    let p = [x, y, z, w];
+                    let expected = (0..4).all(|i| min[i] <= p[i] && p[i] <= max[i]);
+                    assert_eq!(zi.contains(morton_4_signed(x, y, z, w)), expected, "{p:?}");
+                    inside += expected as usize;
+                }
+            }
+        }
     }
+    assert_eq!(inside, 5 * 5 * 2 * 10);
+    assert_eq!(ZOrderIndexer::<4>::try_new_signed(((1, 0), (0, 0)), ((-1, 0), (0, 0))).err(), Some(InvertedBounds { axis: 0 }));
 }
@@ -0,0 +1,110 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::{AugmentedMap, Cell, Entry, Measure, QuadTree, Rect, SpaceFillingCurve, Store, Visit, Walk};
+
+/// Orders anything by an `f64` rank alone.
+struct Ranked<T>(f64, T);
+
+impl<T> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Ranked<T> {}
+
+impl<T> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+type Item<'a, V> = (&'a (f32, f32), &'a V);
+
+/// The `k` largest entries seen so far.
+struct TopK<'a, V> {
+    k: usize,
+    heap: BinaryHeap<Reverse<Ranked<Item<'a, V>>>>,
+}
+
+impl<'a, V> TopK<'a, V> {
+    fn push(&mut self, rank: f64, entry: Item<'a, V>) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(Ranked(rank, entry)));
+        } else if self.heap.peek().is_some_and(|min| min.0.0 < rank) {
+            self.heap.pop();
+            self.heap.push(Reverse(Ranked(rank, entry)));
+        }
+    }
+
+    /// Whether a value no larger than `bound` could still make it in.
+    fn wants(&self, bound: f64) -> bool {
+        self.heap.len() < self.k || self.heap.peek().is_some_and(|min| min.0.0 < bound)
+    }
+
+    fn into_sorted(self) -> Vec<Item<'a, V>> {
+        self.heap.into_sorted_vec().into_iter().map(|Reverse(Ranked(_, e))| e).collect()
+    }
+}
+
+impl<V, S: Store<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, S, C> {
+    /// The `k` entries inside `rect` with the largest `key_fn`, largest first,
+    /// from a bounded heap over the query. An arbitrary `key_fn` has no bounds on
+    /// whole cells, so every entry in `rect` is visited. To skip cells, keep the
+    /// value in an `AugmentedMap` measure and call `top_k_by_measure`.
+    pub fn top_k_in_range<F>(&self, rect: Rect, k: usize, key_fn: F) -> Vec<(&(f32, f32), &V)>
+    where
+        F: Fn(&V) -> f64,
+    {
+        let mut top = TopK { k, heap: BinaryHeap::with_capacity(k + 1) };
+        if k > 0 {
+            for e in self.try_query_rect(rect).into_iter().flatten() {
+                top.push(key_fn(e.1), e);
+            }
+        }
+        top.into_sorted()
+    }
+}
+
+impl<V, M: Measure<Entry<V>>, C: SpaceFillingCurve> QuadTree<V, AugmentedMap<Entry<V>, M>, C> {
+    /// The `k` entries inside `rect` with the largest measured value, the one
+    /// `Measure::upper_bound` reports for the entry alone, largest first.
+    ///
+    /// When the measure keeps maximums, like `Max` or `Stats`, cells whose
+    /// maximum cannot beat the current top k are skipped whole. Otherwise
+    /// every entry ranks the same, and the first `k` in the rectangle are kept.
+    pub fn top_k_by_measure(&self, rect: Rect, k: usize) -> Vec<(&(f32, f32), &V)> {
+        let rank = |e: &Entry<V>| M::measure(e).upper_bound().unwrap_or(f64::NEG_INFINITY);
+        let mut top = TopK { k, heap: BinaryHeap::with_capacity(k + 1) };
+        if k == 0 {
+            return Vec::new();
+        }
+        if self.tree.upper_bound(..).is_none() {
+            for (p, v) in self.try_query_rect(rect).into_iter().flatten() {
+                top.push(f64::NEG_INFINITY, (p, v));
+            }
+            return top.into_sorted();
+        }
+        let bound = |cell: &Cell| {
+            let (start, end) = cell.key_range();
+            let nonempty = self.tree.count_at_most(start ..= end, 1) > 0;
+            nonempty.then(|| self.tree.upper_bound(start ..= end)).flatten().map(|b| Ranked(b, ()))
+        };
+        self.walk_cells(rect, bound, |visit| match visit {
+            Visit::Cell { priority, .. } if !top.wants(priority.0) => Walk::Stop,
+            Visit::Cell { .. } => Walk::Descend,
+            Visit::Entry(e) => {
+                top.push(rank(e), (&e.0, &e.1));
+                Walk::Descend
+            }
+        });
+        top.into_sorted()
+    }
+}